@@ -1,8 +1,7 @@
-use std::fs::read;
+extern crate intcode;
 
-const FINISH: u64 = 99;
-const ADD: u64 = 1;
-const MULTIPLY: u64 = 2;
+use std::fs::read;
+use intcode::get_program;
 
 fn main() {
     let raw_input = match read("input.txt") {
@@ -30,32 +29,14 @@ fn main() {
     }
 }
 
-fn get_program(input: String) -> Vec<u64> {
-    return input.split(',').map(|c| match (*c).parse::<u64>() {
-        Err(_) => panic!("Couldn't parse number {}", c),
-        Ok(num) => num,
-    }).collect();
-}
-
-fn run_program(program_param: Vec<u64>) -> Vec<u64> {
-
-    let mut program = program_param.clone();
-    let mut pic = 0;
-    while program[pic] != FINISH {
-        let op1_pos = program[pic + 1] as usize;
-        let op2_pos = program[pic + 2] as usize;
-        let result_pos = program[pic + 3] as usize;
-
-        program[result_pos] = match program[pic] {
-            ADD => program[op1_pos] + program[op2_pos],
-            MULTIPLY => program[op1_pos] * program[op2_pos],
-            _ => panic!("Invalid command: {}", program[pic])
-        };
+fn run_program(program_param: Vec<i64>) -> Vec<i64> {
 
-        pic += 4;
+    let mut vm = intcode::Vm::new(program_param);
+    if let Err(error) = vm.run_to_end() {
+        panic!("{}", error);
     }
 
-    return program;
+    return vm.memory.program().to_vec();
 }
 
 #[cfg(test)]
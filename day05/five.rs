@@ -1,38 +1,7 @@
-use std::convert::From;
-use std::fs::read;
-
-const FINISH: i64 = 99;
-const ADD: i64 = 1;
-const MULTIPLY: i64 = 2;
-const INPUT: i64 = 3;
-const OUTPUT: i64 = 4;
-const JMP_TRUE: i64 = 5;
-const JMP_FALSE: i64 = 6;
-const LESS_THAN: i64 = 7;
-const EQUALS: i64 = 8;
+extern crate intcode;
 
-#[derive(PartialEq, Debug)]
-enum ParameterMode {
-    PositionMode,
-    ImmediateMode,
-}
-
-impl From<i64> for ParameterMode {
-    fn from(number: i64) -> Self {
-        match number {
-            0 => ParameterMode::PositionMode,
-            1 => ParameterMode::ImmediateMode,
-            _ => panic!("Invalid parameter mode"),
-        }
-    }
-}
-
-struct Instruction {
-    opcode: i64,
-    par1mode: ParameterMode,
-    par2mode: ParameterMode,
-    par3mode: ParameterMode,
-}
+use std::fs::read;
+use intcode::get_program;
 
 fn main() {
     let raw_input = match read("input.txt") {
@@ -49,133 +18,15 @@ fn main() {
     println!("{:?}", output);
 }
 
-fn get_program(input: String) -> Vec<i64> {
-    return input.split(',').map(|c| match (*c).parse::<i64>() {
-        Err(_) => panic!("Couldn't parse number {}", c),
-        Ok(num) => num,
-    }).collect();
-}
-
 fn run_program(program_param: Vec<i64>, input_param: Vec<i64>) -> Vec<i64> {
 
-    let mut program = program_param.clone();
-    let mut input = input_param.clone();
-    let mut output = Vec::<i64>::new();
-
-    let mut pic: usize = 0;
-    while program[pic] != FINISH {
-
-        let instruction = parse_instruction(program[pic]);
-
-        match instruction.opcode {
-            ADD => {
-                let (param1, param2) = load_params(&program, pic, instruction);
-
-                let dest: usize = program[pic + 3] as usize;
-                program[dest] = param1 + param2;
-                pic += 4;
-            },
-            MULTIPLY => {
-                let (param1, param2) = load_params(&program, pic, instruction);
-
-                let dest: usize = program[pic + 3] as usize;
-                program[dest] = param1 * param2;
-                pic += 4;
-            },
-            INPUT => {
-                let input_number: i64 = match input.pop() {
-                    Some(num) => num,
-                    None => panic!("Tried to read input number, but no input was available"),
-                };
-
-                let dest: usize = program[pic + 1] as usize;
-                program[dest] = input_number;
-                pic += 2;
-            },
-            OUTPUT => {
-                let param1 = match instruction.par1mode {
-                    ParameterMode::PositionMode => program[program[pic + 1] as usize],
-                    ParameterMode::ImmediateMode => program[pic + 1],
-                };
+    let mut vm = intcode::Vm::new(program_param);
+    vm.push_inputs(&input_param);
 
-                output.push(param1);
-                pic += 2;
-            },
-            JMP_TRUE => {
-                let (param1, param2) = load_params(&program, pic, instruction);
-
-                if param1 != 0 {
-                    pic = param2 as usize;
-                }
-                else {
-                    pic += 3;
-                }
-            },
-            JMP_FALSE => {
-                let (param1, param2) = load_params(&program, pic, instruction);
-
-                if param1 == 0 {
-                    pic = param2 as usize;
-                }
-                else {
-                    pic += 3;
-                }
-            },
-            LESS_THAN => {
-                let (param1, param2) = load_params(&program, pic, instruction);
-                let dest = program[pic + 3] as usize;
-
-                if param1 < param2 {
-                    program[dest] = 1;
-                }
-                else {
-                    program[dest] = 0;
-                }
-
-                pic += 4;
-            },
-            EQUALS => {
-                let (param1, param2) = load_params(&program, pic, instruction);
-                let dest = program[pic + 3] as usize;
-
-                if param1 == param2 {
-                    program[dest] = 1;
-                }
-                else {
-                    program[dest] = 0;
-                }
-
-                pic += 4;
-            }
-            _ => panic!("Unknown opcode: {}", instruction.opcode),
-        };
-
-    }
-
-    return output;
-}
-
-fn parse_instruction(code: i64) -> Instruction {
-    return Instruction {
-        opcode: code % 100,
-        par1mode: ParameterMode::from((code / 100) % 10),
-        par2mode: ParameterMode::from((code / 1000) % 10),
-        par3mode: ParameterMode::from((code / 10000) % 10),
-    };
-}
-
-fn load_params(program: &Vec<i64>, pic: usize, instruction: Instruction) -> (i64, i64) {
-    let param1 = match instruction.par1mode {
-        ParameterMode::PositionMode => program[program[pic + 1] as usize],
-        ParameterMode::ImmediateMode => program[pic + 1],
-    };
-
-    let param2 = match instruction.par2mode {
-        ParameterMode::PositionMode => program[program[pic + 2] as usize],
-        ParameterMode::ImmediateMode => program[pic + 2],
+    return match vm.run_to_end() {
+        Ok(output) => output,
+        Err(error) => panic!("{}", error),
     };
-
-    return (param1, param2);
 }
 
 
@@ -183,6 +34,7 @@ fn load_params(program: &Vec<i64>, pic: usize, instruction: Instruction) -> (i64
 mod tests {
 
     use super::*;
+    use intcode::{parse_instruction, Instruction, ParameterMode};
 
     #[test]
     fn test_parse_instruction() {
@@ -1,48 +1,11 @@
-use std::convert::From;
-use std::fs::read;
-use std::collections::HashMap;
-
-const FINISH: i64 = 99;
-const ADD: i64 = 1;
-const MULTIPLY: i64 = 2;
-const INPUT: i64 = 3;
-const OUTPUT: i64 = 4;
-const JMP_TRUE: i64 = 5;
-const JMP_FALSE: i64 = 6;
-const LESS_THAN: i64 = 7;
-const EQUALS: i64 = 8;
-const ADJUST_BASE: i64 = 9;
-
-#[derive(PartialEq, Debug)]
-enum ParameterMode {
-    PositionMode,
-    ImmediateMode,
-    RelativeMode,
-}
-
-impl From<i64> for ParameterMode {
-    fn from(number: i64) -> Self {
-        match number {
-            0 => ParameterMode::PositionMode,
-            1 => ParameterMode::ImmediateMode,
-            2 => ParameterMode:: RelativeMode,
-            _ => panic!("Invalid parameter mode"),
-        }
-    }
-}
-
-struct Instruction {
-    opcode: i64,
-    par1mode: ParameterMode,
-    par2mode: ParameterMode,
-    par3mode: ParameterMode,
-}
+extern crate intcode;
 
-struct Memory {
-    program: Vec<i64>,
-    virtual_memory: HashMap<usize, i64>,
-    relative_base: usize,
-}
+use std::env;
+use std::fs::{read, File};
+use std::io::BufWriter;
+use std::path::Path;
+use std::collections::HashMap;
+use intcode::{get_program, group_output, Patches, StepResult, Tracer};
 
 // Arcade
 
@@ -58,7 +21,7 @@ struct Arcade {
     ball_x: i64,
     ball_y: i64,
     paddle_x: i64,
-    memory: Memory,
+    vm: intcode::Vm,
 }
 
 fn main() {
@@ -68,57 +31,62 @@ fn main() {
     };
 
     let input_string = String::from_utf8_lossy(&raw_input);
-    let input_program = get_program(input_string.to_string());
-    
+    let mut input_program = get_program(input_string.to_string());
+
+    // Insert coin
+    let coin = match Patches::load(Path::new("coin.patch")) {
+        Err(error) => panic!("Can't read coin.patch: {}", error),
+        Ok(patches) => patches,
+    };
+    if let Err(error) = coin.apply(&mut input_program) {
+        panic!("Can't insert coin: {}", error);
+    }
+
     let mut arcade = Arcade {
         painted_positions: HashMap::new(),
         score: 0, 
         ball_x: 0,
         ball_y: 0,
         paddle_x: 0,
-        memory: Memory{
-            program: input_program.clone(), 
-            virtual_memory: HashMap::new(),
-            relative_base: 0,
-        },
+        vm: intcode::Vm::new(input_program.clone()),
     };
 
-    // Insert coin
-    arcade.memory.write_memory(0, 2);
+    // Executed instructions are traced to the file given as the first argument
+    let mut tracer = env::args().nth(1).map(|path| match File::create(&path) {
+        Err(_) => panic!("Can't create {}!", path),
+        Ok(file) => Tracer::new(BufWriter::new(file)),
+    });
 
-    let mut continue_from = Some(0);
-    let mut input = vec![];
-    let mut ouput_tile = vec![];
-    while continue_from.is_some() {
+    loop {
 
-        let (ip, output) = run_program(&mut arcade.memory, &input, continue_from.unwrap());
-        
-        continue_from = ip;
+        let (output, state) = arcade.vm.run_until_input_observed(&mut tracer).unwrap_or_else(|error| panic!("{}", error));
 
-        if continue_from.is_some() {
-            input = vec![];
-            if output.is_empty() {
-                // Program stopped and there is no output: 
-                if arcade.ball_x < arcade.paddle_x {
-                    input = vec![-1];
-                } else if arcade.ball_x > arcade.paddle_x {
-                    input = vec![1];
-                } else {
-                    input = vec![0];
-                }
-            }
-            else if ouput_tile.len() < 2 {
-                ouput_tile.push(output[0]);
-            }
-            else if ouput_tile.len() == 2 {
-                arcade.process_output(ouput_tile[0], ouput_tile[1], output[0]);
-                ouput_tile = vec![];
-            }
+        for output_tile in group_output(&output, 3) {
+            arcade.process_output(output_tile[0], output_tile[1], output_tile[2]);
+        }
+
+        if state == StepResult::Halted {
+            break;
+        }
+
+        // Move the joystick towards the ball
+        if arcade.ball_x < arcade.paddle_x {
+            arcade.vm.push_input(-1);
+        } else if arcade.ball_x > arcade.paddle_x {
+            arcade.vm.push_input(1);
+        } else {
+            arcade.vm.push_input(0);
         }
     }
     
     println!("SCORE: {}", arcade.score);
 
+    if let Some(tracer) = tracer {
+        if let Err(error) = tracer.finish() {
+            panic!("Couldn't write the trace: {}", error);
+        }
+    }
+
     paint_screen(arcade.painted_positions);
 }
 
@@ -145,202 +113,6 @@ impl Arcade {
     }
 }
 
-fn get_program(input: String) -> Vec<i64> {
-    return input.split(',').map(|c| match (*c).parse::<i64>() {
-        Err(_) => panic!("Couldn't parse number {}", c),
-        Ok(num) => num,
-    }).collect();
-}
-
-fn run_program(memory: &mut Memory, input_param: &Vec<i64>, ip: usize) -> (Option<usize>, Vec<i64>) {
-
-    let mut input = input_param.clone();
-
-    let mut pic: usize = ip;
-    while memory.program[pic] != FINISH {
-
-        let instruction = parse_instruction(memory.program[pic]);
-
-        match instruction.opcode {
-            ADD => {
-                let (param1, param2, dest) = memory.load_three_params(pic, memory.relative_base, instruction);
-
-                memory.write_memory(dest as usize, param1 + param2);
-                pic += 4;
-            },
-            MULTIPLY => {
-                let (param1, param2, dest) = memory.load_three_params(pic, memory.relative_base, instruction);
-
-                memory.write_memory(dest as usize, param1 * param2);
-                pic += 4;
-            },
-            INPUT => {
-                let input_number: i64 = match input.pop() {
-                    Some(num) => num,
-                    None => {
-                        // If there is no input available, switch to different program
-                        return (Some(pic), vec![]);
-                    },
-                };
-
-                let position1 = memory.read_memory(pic + 1);
-                let dest = match instruction.par1mode {
-                    ParameterMode::PositionMode => position1,
-                    ParameterMode::ImmediateMode => position1,
-                    ParameterMode::RelativeMode => (memory.relative_base as i64 + position1),
-                };
-
-                memory.write_memory(dest as usize, input_number);
-                pic += 2;
-            },
-            OUTPUT => {
-                let param1 = memory.load_one_param(pic, memory.relative_base, instruction);
-                pic += 2;
-
-                return (Some(pic), vec![param1]);
-            },
-            JMP_TRUE => {
-                let (param1, param2) = memory.load_two_params(pic, memory.relative_base, instruction);
-
-                if param1 != 0 {
-                    pic = param2 as usize;
-                }
-                else {
-                    pic += 3;
-                }
-            },
-            JMP_FALSE => {
-                let (param1, param2) = memory.load_two_params(pic, memory.relative_base, instruction);
-
-                if param1 == 0 {
-                    pic = param2 as usize;
-                }
-                else {
-                    pic += 3;
-                }
-            },
-            LESS_THAN => {
-                let (param1, param2, dest) = memory.load_three_params(pic, memory.relative_base, instruction);
-
-                if param1 < param2 {
-                    memory.write_memory(dest as usize, 1);
-                }
-                else {
-                    memory.write_memory(dest as usize, 0);
-                }
-
-                pic += 4;
-            },
-            EQUALS => {
-                let (param1, param2, dest) = memory.load_three_params(pic, memory.relative_base, instruction);
-
-                if param1 == param2 {
-                    memory.write_memory(dest as usize, 1);
-                }
-                else {
-                    memory.write_memory(dest as usize, 0);
-                }
-
-                pic += 4;
-            }
-            ADJUST_BASE => {
-                let param1 = memory.load_one_param(pic, memory.relative_base, instruction);
-                memory.relative_base = (memory.relative_base as i64 + param1) as usize;
-                
-                pic += 2;
-            }
-            _ => panic!("Unknown opcode: {}", instruction.opcode),
-        };
-
-    }
-
-    return (None, vec![]);
-}
-
-fn parse_instruction(code: i64) -> Instruction {
-    return Instruction {
-        opcode: code % 100,
-        par1mode: ParameterMode::from((code / 100) % 10),
-        par2mode: ParameterMode::from((code / 1000) % 10),
-        par3mode: ParameterMode::from((code / 10000) % 10),
-    };
-}
-
-impl Memory {
-    fn read_memory(&self, location: usize) -> i64 {
-        if location < self.program.len() {
-            return self.program[location];
-        }
-        else {
-            return match self.virtual_memory.get(&location) {
-                Some(value) => *value,
-                None => 0,
-            };
-        }
-    }
-
-    fn write_memory(&mut self, location: usize, value: i64) {
-
-        if location < self.program.len() {
-            self.program[location] = value;
-        } else {
-            self.virtual_memory.insert(location, value);
-        }
-    }
-
-    fn load_one_param(&self, pic: usize, relative_base: usize, instruction: Instruction) -> i64 {
-        let position1 = self.read_memory(pic + 1);
-        return match instruction.par1mode {
-            ParameterMode::PositionMode => self.read_memory(position1 as usize),
-            ParameterMode::ImmediateMode => position1,
-            ParameterMode::RelativeMode => self.read_memory((relative_base as i64 + position1) as usize),
-        };
-    }
-    
-    fn load_two_params(&self, pic: usize, relative_base: usize, instruction: Instruction) -> (i64, i64) {
-        let position1 = self.read_memory(pic + 1);
-        let param1 = match instruction.par1mode {
-            ParameterMode::PositionMode => self.read_memory(position1 as usize),
-            ParameterMode::ImmediateMode => position1,
-            ParameterMode::RelativeMode => self.read_memory((relative_base as i64 + position1) as usize),
-        };
-    
-        let position2 = self.read_memory(pic + 2);
-        let param2 = match instruction.par2mode {
-            ParameterMode::PositionMode => self.read_memory(position2 as usize),
-            ParameterMode::ImmediateMode => position2,
-            ParameterMode::RelativeMode => self.read_memory((relative_base as i64 + position2) as usize),
-        };
-    
-        return (param1, param2);
-    }
-    
-    fn load_three_params(&self, pic: usize, relative_base: usize, instruction: Instruction) -> (i64, i64, i64) {
-        let position1 = self.read_memory(pic + 1);
-        let param1 = match instruction.par1mode {
-            ParameterMode::PositionMode => self.read_memory(position1 as usize),
-            ParameterMode::ImmediateMode => position1,
-            ParameterMode::RelativeMode => self.read_memory((relative_base as i64 + position1) as usize),
-        };
-    
-        let position2 = self.read_memory(pic + 2);
-        let param2 = match instruction.par2mode {
-            ParameterMode::PositionMode => self.read_memory(position2 as usize),
-            ParameterMode::ImmediateMode => position2,
-            ParameterMode::RelativeMode => self.read_memory((relative_base as i64 + position2) as usize),
-        };
-    
-        let position3 = self.read_memory(pic + 3);
-        let param3 = match instruction.par3mode {
-            ParameterMode::PositionMode => position3,
-            ParameterMode::ImmediateMode => position3,
-            ParameterMode::RelativeMode =>  (relative_base as i64 + position3),
-        };
-    
-        return (param1, param2, param3);
-    }
-}
-
 fn paint_screen(painted: HashMap<(i64, i64), i64>) {
     const cols: usize = 45;
     const rows: usize = 23;
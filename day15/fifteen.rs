@@ -1,10 +1,10 @@
 extern crate pathfinding;
+extern crate intcode;
 
 use std::fs::read;
 use std::collections::{HashMap, VecDeque};
 use pathfinding::prelude::dijkstra_all;
-
-mod intcode;
+use intcode::{get_program, StepResult};
 
 // Droid
 const NORTH: i64 = 1;
@@ -27,7 +27,7 @@ struct Droid {
     tile_types: HashMap<(i64,i64), i64>,
     unchecked_tiles: VecDeque<(i64, i64)>,
     position: (i64, i64),
-    memory: intcode::Memory,
+    vm: intcode::Vm,
 }
 
 fn main() {
@@ -44,59 +44,52 @@ fn main() {
         tile_types: HashMap::new(),
         unchecked_tiles: VecDeque::new(),
         position: (0, 0),
-        memory: intcode::Memory{
-            program: input_program.clone(), 
-            virtual_memory: HashMap::new(),
-            relative_base: 0,
-        },
+        vm: intcode::Vm::new(input_program.clone()),
     };
 
     droid.add_all_adjecent();
 
     let mut oxygen_system_position = None;
-    let mut continue_from = Some(0);
-    let mut input = vec![];
     let mut current_path = droid.path_to_next();
-    while continue_from.is_some() && current_path.is_some() {
-
-        let (ip, output) = intcode::run_program(&mut droid.memory, &input, continue_from.unwrap());      
-        continue_from = ip;
-        input.clear();
-
-        if output.is_empty() {
-            // If there's no output, we're making a move
-            if current_path.as_ref().map(|p| p.is_complete()).unwrap() {
-                droid.add_all_adjecent();
-                current_path = droid.path_to_next();
-            }
+    while current_path.is_some() {
 
-            if current_path.is_some() {
-                let next_move = current_path.as_mut().map(|p| p.next_move()).unwrap();
-                input.push(next_move);
-            }
-        }
-        else {
-            assert_eq!(output.len(), 1);
-            let sensor = output.first().unwrap();
+        let result = droid.vm.run().unwrap_or_else(|error| panic!("{}", error));
 
-            match *sensor {
-                HIT_WALL => {
-                    // Don't change the position, and instead just calculate path to next unchecked tile
-                    droid.tile_types.insert(current_path.as_ref().map(|p| p.current_position()).unwrap(), *sensor);
+        match result {
+            StepResult::NeedInput => {
+                // The droid is waiting for its next move
+                if current_path.as_ref().map(|p| p.is_complete()).unwrap() {
+                    droid.add_all_adjecent();
                     current_path = droid.path_to_next();
-                },
-                MOVED => {
-                    // Was able to move to next position
-                    droid.tile_types.insert(droid.position, *sensor);
-                    droid.position = current_path.as_ref().map(|p| p.current_position()).unwrap();
-                },
-                FOUND_OXYGEN => {
-                    droid.tile_types.insert(droid.position, *sensor);
-                    droid.position = current_path.as_ref().map(|p| p.current_position()).unwrap();
-                    oxygen_system_position = Some(droid.position);
-                },
-                _ => panic!("Unexpected output."),
-            };
+                }
+
+                if current_path.is_some() {
+                    let next_move = current_path.as_mut().map(|p| p.next_move()).unwrap();
+                    droid.vm.push_input(next_move);
+                }
+            },
+            StepResult::Output(sensor) => {
+                match sensor {
+                    HIT_WALL => {
+                        // Don't change the position, and instead just calculate path to next unchecked tile
+                        droid.tile_types.insert(current_path.as_ref().map(|p| p.current_position()).unwrap(), sensor);
+                        current_path = droid.path_to_next();
+                    },
+                    MOVED => {
+                        // Was able to move to next position
+                        droid.tile_types.insert(droid.position, sensor);
+                        droid.position = current_path.as_ref().map(|p| p.current_position()).unwrap();
+                    },
+                    FOUND_OXYGEN => {
+                        droid.tile_types.insert(droid.position, sensor);
+                        droid.position = current_path.as_ref().map(|p| p.current_position()).unwrap();
+                        oxygen_system_position = Some(droid.position);
+                    },
+                    _ => panic!("Unexpected output."),
+                };
+            },
+            StepResult::Halted => break,
+            StepResult::OutOfFuel { ip } => panic!("Droid ran out of fuel at {}", ip),
         }
     }
 
@@ -234,13 +227,6 @@ impl Path {
     }
 }
 
-fn get_program(input: String) -> Vec<i64> {
-    return input.split(',').map(|c| match (*c).parse::<i64>() {
-        Err(_) => panic!("Couldn't parse number {}", c),
-        Ok(num) => num,
-    }).collect();
-}
-
 fn paint_screen(tiles: HashMap<(i64, i64), i64>,) {
     const cols: usize = 300;
     const rows: usize = 50;
@@ -294,11 +280,7 @@ mod test {
             tile_types: HashMap::new(),
             position: (4, 2),
             unchecked_tiles: unchecked,
-            memory: intcode::Memory {
-                program: Vec::new(),
-                virtual_memory: HashMap::new(),
-                relative_base: 0,
-            },
+            vm: intcode::Vm::new(Vec::new()),
         };
 
         let path = droid.path_to_next();
@@ -1,11 +1,11 @@
 extern crate itertools;
+extern crate intcode;
 
 use std::fs::read;
-use std::collections::HashMap;
+use std::path::Path;
 use itertools::Itertools;
 use itertools::repeat_n;
-
-mod intcode;
+use intcode::{get_program, AsciiVm, Patches, StepResult};
 
 const SCAFFOLD: i64 = 35;
 const SPACE: i64 = 46;
@@ -20,38 +20,17 @@ fn main() {
     let input_string = String::from_utf8_lossy(&raw_input);
     let input_program = get_program(input_string.to_string());
 
-    let mut memory = intcode::Memory{
-        program: input_program.clone(), 
-        virtual_memory: HashMap::new(),
-        relative_base: 0,
-    };
-
-    let mut image = vec![];
-    let mut current_row = vec![];
-
-    let mut continue_from = Some(0);
-    let mut input = vec![];
-    while continue_from.is_some() {
+    let mut camera = AsciiVm::new(input_program.clone());
 
-        let (ip, output) = intcode::run_program(&mut memory, &input, continue_from.unwrap());      
-        continue_from = ip;
-        input.clear();
-
-        if !output.is_empty() {
-            let pixel = output[0];
-            if pixel == 10 {
-                image.push(current_row);
-                current_row = vec![];
-            }
-            else {
-                current_row.push(pixel);
-            }
-        }
+    let state = camera.run().unwrap_or_else(|error| panic!("{}", error));
+    if state == StepResult::NeedInput {
+        panic!("Camera is not expecting any input");
     }
-    image.pop();
+
+    let image = camera.image();
 
     println!("Alignment parameters: {}", alignment_parameters(&image));
-    print_image(&image);
+    print!("{}", camera.render());
 
     // Part 2
     let path = find_path(&image);
@@ -62,34 +41,29 @@ fn main() {
     println!("A: {}, B: {}, C: {}", a, b, c);
 
 
-    let mut memory = intcode::Memory{
-        program: input_program.clone(), 
-        virtual_memory: HashMap::new(),
-        relative_base: 0,
+    // Wake the robot up
+    let wake = match Patches::load(Path::new("wake.patch")) {
+        Err(error) => panic!("Can't read wake.patch: {}", error),
+        Ok(patches) => patches,
     };
-    memory.program[0] = 2;
-
-    let mut continue_from = Some(0);
-    let complete_input = format!("{}\n{}\n{}\n{}\nn\n", pattern, a, b, c);
-    let mut input_iter: Vec<i64> = complete_input.chars().into_iter().map(|c| c as i64).rev().collect();
-    let mut input: Vec<i64> = vec![];
-    let mut last_output = 0;
-    while continue_from.is_some() {
-
-        let (ip, output) = intcode::run_program(&mut memory, &input, continue_from.unwrap());      
-        continue_from = ip;
-        input.clear();
-
-        if output.is_empty() {
-            if !input_iter.is_empty() {
-                input = vec![input_iter.pop().unwrap()];
-            }
-        }
-        else {
-            last_output = *output.first().unwrap();
-        }
+    let mut robot_program = input_program.clone();
+    if let Err(error) = wake.apply(&mut robot_program) {
+        panic!("Can't wake the robot: {}", error);
+    }
+
+    let mut robot = AsciiVm::new(robot_program);
+
+    for line in &[pattern, a, b, c, String::from("n")] {
+        robot.write_line(line);
+    }
+
+    let state = robot.run().unwrap_or_else(|error| panic!("{}", error));
+    if state == StepResult::NeedInput {
+        panic!("Robot is expecting more input");
     }
 
+    let last_output = *robot.values().last().unwrap();
+
     println!("Dust collected: {}", last_output);
 }
 
@@ -350,20 +324,6 @@ fn alignment_parameters(image: &Vec<Vec<i64>>) -> u64 {
     return sum as u64;
 }
 
-fn print_image(image: &Vec<Vec<i64>>) {
-    for row in image {
-        let row_strings: Vec<String> = row.into_iter().map(|x| (*x as u8 as char).to_string()).collect();
-        println!("{}", row_strings.join(""));
-    }
-}
-
-fn get_program(input: String) -> Vec<i64> {
-    return input.split(',').map(|c| match (*c).parse::<i64>() {
-        Err(_) => panic!("Couldn't parse number {}", c),
-        Ok(num) => num,
-    }).collect();
-}
-
 #[cfg(test)]
 mod tests {
 
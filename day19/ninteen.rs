@@ -1,10 +1,10 @@
 extern crate itertools;
+extern crate intcode;
 
 use std::fs::read;
 use std::collections::HashMap;
 use itertools::Itertools;
-
-mod intcode;
+use intcode::{get_program, StepResult};
 
 const SHIP_SIZE: i64 = 100;
 
@@ -25,6 +25,7 @@ fn main() {
 
     let input_string = String::from_utf8_lossy(&raw_input);
     let input_program = get_program(input_string.to_string());
+    let drone = intcode::Vm::new(input_program);
 
     let mut points_to_check: Vec<(i64, i64)> = vec![];
     let mut current_edge = (5, 8);
@@ -37,58 +38,51 @@ fn main() {
             points_to_check = vec![(x + 1, y + 1)];
         }
 
-        let mut memory = intcode::Memory{
-            program: input_program.clone(), 
-            virtual_memory: HashMap::new(),
-            relative_base: 0,
-        };
+        let mut vm = drone.clone();
 
-        let mut continue_from = Some(0);
         let next = points_to_check.pop().unwrap();
-        let mut input = vec![next.1, next.0];
-
-        while continue_from.is_some() {
-
-            let (ip, output) = intcode::run_program(&mut memory, &input, continue_from.unwrap());      
-            continue_from = ip;
-
-            if output.is_empty() {
-                input.pop();
-            }
-            else {
-                assert_eq!(output.len(), 1);
-                let sensor = output.first().unwrap();
-
-                if current_mode == Mode::FindNewEdge {
-                    if *sensor == PULL {
-                        current_edge = (x + 1, y + 1);
-                    }
-                    else {
-                        current_edge = (x, y + 1);
-                    }
-
-                    println!("New edge: {:?}", current_edge);
-
-                    if x < SHIP_SIZE {
-                        current_mode = Mode::FindNewEdge;
+        vm.push_inputs(&[next.0, next.1]);
+
+        loop {
+
+            let result = vm.run().unwrap_or_else(|error| panic!("{}", error));
+
+            match result {
+                StepResult::NeedInput => panic!("Drone is expecting more input"),
+                StepResult::Output(sensor) => {
+                    if current_mode == Mode::FindNewEdge {
+                        if sensor == PULL {
+                            current_edge = (x + 1, y + 1);
+                        }
+                        else {
+                            current_edge = (x, y + 1);
+                        }
+
+                        println!("New edge: {:?}", current_edge);
+
+                        if x < SHIP_SIZE {
+                            current_mode = Mode::FindNewEdge;
+                        }
+                        else {
+                            current_mode = Mode::CheckPoints;
+
+                            let (new_x, new_y) = current_edge;
+                            points_to_check = generate_points(new_x, new_y);
+                        }
                     }
                     else {
-                        current_mode = Mode::CheckPoints;
-
-                        let (new_x, new_y) = current_edge;
-                        points_to_check = generate_points(new_x, new_y);
-                    }
-                }
-                else {
-                    if points_to_check.is_empty() {
-                        println!("The first fit for Santa's ship is X:{}, Y: {}", x-SHIP_SIZE+1, y);
-                        break 'main_loop;
+                        if points_to_check.is_empty() {
+                            println!("The first fit for Santa's ship is X:{}, Y: {}", x-SHIP_SIZE+1, y);
+                            break 'main_loop;
+                        }
+
+                        if sensor == NO_PULL {
+                            current_mode = Mode::FindNewEdge;
+                        }
                     }
-
-                    if *sensor == NO_PULL {
-                        current_mode = Mode::FindNewEdge;
-                    }
-                }
+                },
+                StepResult::Halted => break,
+                StepResult::OutOfFuel { ip } => panic!("Drone ran out of fuel at {}", ip),
             }
         }
     }
@@ -107,13 +101,6 @@ fn generate_points(x: i64, y: i64) -> Vec<(i64, i64)> {
     return points;
 }
 
-fn get_program(input: String) -> Vec<i64> {
-    return input.split(',').map(|c| match (*c).parse::<i64>() {
-        Err(_) => panic!("Couldn't parse number {}", c),
-        Ok(num) => num,
-    }).collect();
-}
-
 fn paint_screen(tiles: HashMap<(i64, i64), i64>) -> Vec<String> {
     const cols: usize = 100;
     const rows: usize = 100;
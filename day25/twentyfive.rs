@@ -1,10 +1,14 @@
 
 #[macro_use] extern crate text_io;
+extern crate intcode;
 
+use std::env;
 use std::fs::read;
-use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::Path;
+use intcode::{get_program, AsciiVm, Coverage, StepResult};
 
-mod intcode;
+const CHECKPOINT: &str = "checkpoint.txt";
 
 fn main() {
 
@@ -16,48 +20,54 @@ fn main() {
     let input_string = String::from_utf8_lossy(&raw_input);
     let input_program = get_program(input_string.to_string());
 
-    let mut memory = intcode::Memory{
-        program: input_program.clone(), 
-        virtual_memory: HashMap::new(),
-        relative_base: 0,
-    };
+    let mut droid = AsciiVm::new(input_program.clone());
+
+    // Coverage of the adventure is merged into the file given as the first argument
+    let coverage_path = env::args().nth(1);
+    let mut coverage = coverage_path.as_ref().map(|path| match Coverage::load(Path::new(path)) {
+        Ok(coverage) => coverage,
+        Err(ref error) if error.kind() == ErrorKind::NotFound => Coverage::new(),
+        Err(error) => panic!("Can't read coverage from {}: {}", path, error),
+    });
+
+    loop {
 
-    let mut input_stream = vec![].into_iter();
-    let mut continue_from = Some(0);
-    let mut input = vec![];
-    while continue_from.is_some() {
+        let state = droid.run_observed(&mut coverage).unwrap_or_else(|error| panic!("{}", error));
+        print!("{}", droid.take_text());
 
-        let (ip, output) = intcode::run_program(&mut memory, &input, continue_from.unwrap());      
-        continue_from = ip;
-        input.clear();
+        if let (Some(path), Some(coverage)) = (&coverage_path, &coverage) {
+            if let Err(error) = coverage.save(Path::new(path)) {
+                panic!("Couldn't save coverage: {}", error);
+            }
+        }
 
-        if !output.is_empty() {
-            print!("{}", *output.first().unwrap() as u8 as char);
+        if state == StepResult::Halted {
+            break;
         }
-        else {
-            if let Some(next_char) = input_stream.next() {
-                input.push(next_char);
+
+        let mut line: String = read!("{}\n");
+
+        // Checkpoints of the adventure, which don't reach the droid
+        while line == "save" || line == "load" {
+            if line == "save" {
+                match droid.vm.save(Path::new(CHECKPOINT)) {
+                    Ok(_) => println!("Saved to {}", CHECKPOINT),
+                    Err(error) => println!("Couldn't save: {}", error),
+                }
             }
             else {
-                let line: String = read!("{}\n");
-                let mut temp_input_stream: Vec<i64> = line.chars().map(|c| c as i64).collect();
-                temp_input_stream.push(10);
-                input_stream = temp_input_stream.into_iter();
+                match intcode::Vm::load(Path::new(CHECKPOINT)) {
+                    Ok(vm) => {
+                        droid = AsciiVm::from_vm(vm);
+                        println!("Loaded from {}", CHECKPOINT);
+                    },
+                    Err(error) => println!("Couldn't load: {}", error),
+                }
             }
+
+            line = read!("{}\n");
         }
-    }
-}
 
-fn print_image(image: &Vec<Vec<i64>>) {
-    for row in image {
-        let row_strings: Vec<String> = row.into_iter().map(|x| (*x as u8 as char).to_string()).collect();
-        println!("{}", row_strings.join(""));
+        droid.write_line(&line);
     }
 }
-
-fn get_program(input: String) -> Vec<i64> {
-    return input.split(',').map(|c| match (*c).parse::<i64>() {
-        Err(_) => panic!("Couldn't parse number {}", c),
-        Ok(num) => num,
-    }).collect();
-}
@@ -0,0 +1,155 @@
+use crate::{Observer, Vm, StepResult, VmError};
+
+const NEWLINE: i64 = 10;
+
+/// Adapter for Intcode programs that talk in ASCII. Text is written to the
+/// program line by line, and its output is split into the text shown on the
+/// screen and any values outside of the ASCII range (like a final score).
+#[derive(Clone)]
+pub struct AsciiVm {
+    pub vm: Vm,
+    screen: String,
+    unread: String,
+    values: Vec<i64>,
+}
+
+impl AsciiVm {
+    pub fn new(program: Vec<i64>) -> AsciiVm {
+        AsciiVm::from_vm(Vm::new(program))
+    }
+
+    pub fn from_vm(vm: Vm) -> AsciiVm {
+        AsciiVm { vm, screen: String::new(), unread: String::new(), values: Vec::new() }
+    }
+
+    /// Queues the line as input, followed by a newline.
+    pub fn write_line(&mut self, line: &str) {
+        for c in line.chars() {
+            self.vm.push_input(c as i64);
+        }
+        self.vm.push_input(NEWLINE);
+    }
+
+    /// Runs until the program needs more input than queued or halts.
+    pub fn run(&mut self) -> Result<StepResult, VmError> {
+        self.run_observed(&mut ())
+    }
+
+    pub fn run_observed<O: Observer>(&mut self, observer: &mut O) -> Result<StepResult, VmError> {
+        let (output, state) = self.vm.run_until_input_observed(observer)?;
+        for value in output {
+            self.store(value);
+        }
+
+        Ok(state)
+    }
+
+    /// Returns the next line of text the program printed, without the newline,
+    /// running the program until one is complete. When the program stops before
+    /// printing a newline the unfinished line is returned, or `None` if there
+    /// is no text left.
+    pub fn read_line(&mut self) -> Result<Option<String>, VmError> {
+        loop {
+            if let Some(end) = self.unread.find('\n') {
+                let line = self.unread[..end].to_string();
+                self.unread.drain(..=end);
+                return Ok(Some(line));
+            }
+
+            match self.vm.run()? {
+                StepResult::Output(value) => self.store(value),
+                _ if self.unread.is_empty() => return Ok(None),
+                _ => return Ok(Some(self.take_text())),
+            }
+        }
+    }
+
+    /// Returns all the text that wasn't read yet.
+    pub fn take_text(&mut self) -> String {
+        self.unread.split_off(0)
+    }
+
+    /// Values printed by the program that are not ASCII characters.
+    pub fn values(&self) -> &[i64] {
+        &self.values
+    }
+
+    /// All the text printed so far.
+    pub fn screen(&self) -> &str {
+        &self.screen
+    }
+
+    /// Rows of the screen, without the trailing blank lines.
+    pub fn image(&self) -> Vec<Vec<i64>> {
+        let mut image: Vec<Vec<i64>> = self.screen.lines()
+            .map(|line| line.chars().map(|c| c as i64).collect())
+            .collect();
+
+        while image.last().is_some_and(|row| row.is_empty()) {
+            image.pop();
+        }
+
+        image
+    }
+
+    /// Screen as text, without the trailing blank lines.
+    pub fn render(&self) -> String {
+        self.image().iter()
+            .map(|row| row.iter().map(|value| *value as u8 as char).collect::<String>() + "\n")
+            .collect()
+    }
+
+    pub fn clear_screen(&mut self) {
+        self.screen.clear();
+    }
+
+    fn store(&mut self, value: i64) {
+        if is_ascii(value) {
+            let c = value as u8 as char;
+            self.screen.push(c);
+            self.unread.push(c);
+        }
+        else {
+            self.values.push(value);
+        }
+    }
+}
+
+pub fn is_ascii(value: i64) -> bool {
+    (0..128).contains(&value)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // Echoes every input value back, and prints 1000 after every newline.
+    fn echo_program() -> Vec<i64> {
+        vec![3, 20, 4, 20, 1008, 20, 10, 21, 1005, 21, 14, 1105, 1, 0, 104, 1000, 1105, 1, 0, 0, 0, 0]
+    }
+
+    #[test]
+    fn test_write_and_read_lines() {
+        let mut ascii = AsciiVm::new(echo_program());
+        ascii.write_line("hi");
+        ascii.write_line("there");
+
+        assert_eq!(ascii.read_line(), Ok(Some("hi".to_string())));
+        assert_eq!(ascii.read_line(), Ok(Some("there".to_string())));
+        assert_eq!(ascii.read_line(), Ok(None));
+        assert_eq!(ascii.values(), &[1000, 1000]);
+    }
+
+    #[test]
+    fn test_screen_image() {
+        let mut ascii = AsciiVm::new(vec![104, 35, 104, 46, 104, 10, 104, 46, 104, 35, 104, 10, 104, 10, 99]);
+        assert_eq!(ascii.run(), Ok(StepResult::Halted));
+
+        assert_eq!(ascii.screen(), "#.\n.#\n\n");
+        assert_eq!(ascii.image(), vec![vec![35, 46], vec![46, 35]]);
+        assert_eq!(ascii.render(), "#.\n.#\n");
+        assert_eq!(ascii.take_text(), "#.\n.#\n\n");
+        assert_eq!(ascii.take_text(), "");
+    }
+}
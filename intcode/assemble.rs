@@ -0,0 +1,27 @@
+extern crate intcode;
+
+use std::env;
+use std::fs::read;
+use intcode::assemble;
+
+fn main() {
+
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => panic!("Usage: assemble <source file>"),
+    };
+
+    let raw_input = match read(&path) {
+        Err(_) => panic!("Can't read {}!", path),
+        Ok(file) => file,
+    };
+
+    let source = String::from_utf8_lossy(&raw_input);
+    match assemble(&source) {
+        Ok(program) => {
+            let values: Vec<String> = program.into_iter().map(|value| value.to_string()).collect();
+            println!("{}", values.join(","));
+        },
+        Err(error) => panic!("{}", error),
+    }
+}
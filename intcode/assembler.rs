@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::{parameter_count, ADD, MULTIPLY, INPUT, OUTPUT, JMP_TRUE, JMP_FALSE, LESS_THAN, EQUALS, ADJUST_BASE, FINISH};
+
+/// Problem in the assembler source, with the line (counted from 1) it was found on.
+#[derive(PartialEq, Debug)]
+pub struct AssemblerError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AssemblerError {}
+
+/// Number, or a label with an offset, resolved once all labels are known.
+enum Value {
+    Number(i64),
+    Label(String, i64),
+}
+
+enum Item {
+    Instruction { opcode: i64, operands: Vec<(i64, Value)> },
+    Data(Vec<Value>),
+}
+
+/// Assembles source in the syntax of the disassembler listing into a program.
+/// Every line holds optional labels (`loop:`), followed by an instruction
+/// (`ADD [rb+3], #5 -> [100]`), a `DATA 1, 2, label` directive or an
+/// `ASCII "text"` directive. Operands are written as `#value`, `[address]`
+/// or `[rb+offset]`, where values and addresses can also be labels like
+/// `loop` or `loop+2`. Comments start with `;`. Addresses from a listing,
+/// like `0012:`, are checked against the address the line is assembled to.
+pub fn assemble(source: &str) -> Result<Vec<i64>, AssemblerError> {
+    let mut labels: HashMap<String, i64> = HashMap::new();
+    let mut items: Vec<(usize, Item)> = Vec::new();
+    let mut address: usize = 0;
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message: String| AssemblerError { line: line_number, message };
+
+        let mut rest = strip_comment(line).trim();
+        while let Some(colon) = rest.find(':') {
+            let name = rest[..colon].trim();
+            if is_label(name) {
+                if labels.insert(name.to_string(), address as i64).is_some() {
+                    return Err(error(format!("Label {} is defined twice", name)));
+                }
+            }
+            else if !name.is_empty() && name.chars().all(|c| c.is_ascii_digit()) {
+                if name.parse::<usize>() != Ok(address) {
+                    return Err(error(format!("Address {} doesn't match the assembled address {}", name, address)));
+                }
+            }
+            else {
+                break;
+            }
+            rest = rest[colon + 1..].trim();
+        }
+
+        if rest.is_empty() {
+            continue;
+        }
+
+        let (keyword, arguments) = match rest.find(char::is_whitespace) {
+            Some(space) => (&rest[..space], rest[space..].trim()),
+            None => (rest, ""),
+        };
+
+        let item = match keyword.to_uppercase().as_str() {
+            "DATA" => Item::Data(split_operands(arguments).into_iter().map(parse_value).collect::<Result<_, _>>().map_err(error)?),
+            "ASCII" => Item::Data(parse_string(arguments).map_err(error)?.chars().map(|c| Value::Number(c as i64)).collect()),
+            mnemonic => {
+                let opcode = opcode(mnemonic).ok_or_else(|| error(format!("Unknown mnemonic {}", keyword)))?;
+                let operands: Vec<(i64, Value)> = split_operands(&arguments.replace("->", ","))
+                    .into_iter()
+                    .map(parse_operand)
+                    .collect::<Result<_, _>>()
+                    .map_err(error)?;
+
+                let count = parameter_count(opcode).unwrap();
+                if operands.len() != count {
+                    return Err(error(format!("{} takes {} parameters, found {}", keyword, count, operands.len())));
+                }
+                if (opcode == INPUT || count == 3) && operands[count - 1].0 == 1 {
+                    return Err(error(format!("{} can't write to an immediate value", keyword)));
+                }
+
+                Item::Instruction { opcode, operands }
+            },
+        };
+
+        address += match item {
+            Item::Instruction { ref operands, .. } => operands.len() + 1,
+            Item::Data(ref values) => values.len(),
+        };
+        items.push((line_number, item));
+    }
+
+    let mut program = Vec::with_capacity(address);
+    for (line_number, item) in items {
+        let resolve = |value: &Value| match *value {
+            Value::Number(number) => Ok(number),
+            Value::Label(ref name, offset) => match labels.get(name) {
+                Some(address) => Ok(address + offset),
+                None => Err(AssemblerError { line: line_number, message: format!("Unknown label {}", name) }),
+            },
+        };
+
+        match item {
+            Item::Instruction { opcode, operands } => {
+                let modes = operands.iter().enumerate().map(|(index, operand)| operand.0 * 10i64.pow(index as u32 + 2)).sum::<i64>();
+                program.push(opcode + modes);
+                for (_, value) in &operands {
+                    program.push(resolve(value)?);
+                }
+            },
+            Item::Data(values) => {
+                for value in &values {
+                    program.push(resolve(value)?);
+                }
+            },
+        }
+    }
+
+    Ok(program)
+}
+
+fn opcode(mnemonic: &str) -> Option<i64> {
+    match mnemonic {
+        "ADD" => Some(ADD),
+        "MUL" => Some(MULTIPLY),
+        "IN" => Some(INPUT),
+        "OUT" => Some(OUTPUT),
+        "JT" => Some(JMP_TRUE),
+        "JF" => Some(JMP_FALSE),
+        "LT" => Some(LESS_THAN),
+        "EQ" => Some(EQUALS),
+        "ARB" => Some(ADJUST_BASE),
+        "HALT" => Some(FINISH),
+        _ => None,
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..index],
+            _ => {},
+        }
+    }
+
+    line
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        _ => false,
+    }
+}
+
+fn split_operands(text: &str) -> Vec<&str> {
+    text.split(',').map(|operand| operand.trim()).filter(|operand| !operand.is_empty()).collect()
+}
+
+/// Parses the mode and value of an operand.
+fn parse_operand(text: &str) -> Result<(i64, Value), String> {
+    if let Some(value) = text.strip_prefix('#') {
+        return Ok((1, parse_value(value)?));
+    }
+
+    match text.strip_prefix('[').and_then(|inner| inner.strip_suffix(']')) {
+        Some(inner) => {
+            let inner = inner.trim();
+            if inner == "rb" {
+                Ok((2, Value::Number(0)))
+            }
+            else if inner.starts_with("rb+") || inner.starts_with("rb-") {
+                let offset = parse_value(&inner[3..])?;
+                match offset {
+                    Value::Number(number) if inner.starts_with("rb-") => Ok((2, Value::Number(-number))),
+                    Value::Label(..) if inner.starts_with("rb-") => Err(format!("Label offsets can't be negative in {}", text)),
+                    offset => Ok((2, offset)),
+                }
+            }
+            else {
+                Ok((0, parse_value(inner)?))
+            }
+        },
+        None => Err(format!("Operand {} should be #value, [address] or [rb+offset]", text)),
+    }
+}
+
+/// Parses a number, `label`, `label+offset` or `label-offset`.
+fn parse_value(text: &str) -> Result<Value, String> {
+    let text = text.trim();
+    if let Ok(number) = text.parse::<i64>() {
+        return Ok(Value::Number(number));
+    }
+
+    let (name, offset) = match text.rfind(['+', '-']) {
+        Some(sign) if sign > 0 => match text[sign..].replace('+', "").trim().parse::<i64>() {
+            Ok(offset) => (text[..sign].trim(), offset),
+            Err(_) => return Err(format!("Couldn't parse offset in {}", text)),
+        },
+        _ => (text, 0),
+    };
+
+    if is_label(name) {
+        Ok(Value::Label(name.to_string(), offset))
+    }
+    else {
+        Err(format!("Couldn't parse value {}", text))
+    }
+}
+
+fn parse_string(text: &str) -> Result<String, String> {
+    let inner = match text.strip_prefix('"').and_then(|inner| inner.strip_suffix('"')) {
+        Some(inner) => inner,
+        None => return Err(format!("Expected a quoted string, found {}", text)),
+    };
+
+    let mut string = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            string.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => string.push('\n'),
+            Some('\\') => string.push('\\'),
+            Some('"') => string.push('"'),
+            other => return Err(format!("Unknown escape \\{}", other.map(|c| c.to_string()).unwrap_or_default())),
+        }
+    }
+
+    Ok(string)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{disassemble, Vm};
+
+    #[test]
+    fn test_assemble() {
+        let source = "
+            ; Prints the input if it's not zero
+            start:  IN -> [value]
+                    JF [value], #end
+                    OUT [value]
+                    ADD [rb-1], #5 -> [rb+2]
+            end:    HALT
+            value:  DATA 0, end+1
+        ";
+        assert_eq!(assemble(source), Ok(vec![3, 12, 1006, 12, 11, 4, 12, 21201, -1, 5, 2, 99, 0, 12]));
+    }
+
+    #[test]
+    fn test_ascii() {
+        let source = "
+                    ARB #text
+            loop:   JF [rb], #done
+                    OUT [rb]
+                    ARB #1
+                    JT #1, #loop
+            done:   HALT
+            text:   ASCII \"Hi; \\\"there\\\"\\n\"
+                    DATA 0
+        ";
+        let program = assemble(source).unwrap();
+        let output: String = Vm::new(program).run_to_end().unwrap().into_iter().map(|value| value as u8 as char).collect();
+        assert_eq!(output, "Hi; \"there\"\n");
+    }
+
+    #[test]
+    fn test_reassemble_listing() {
+        let program = vec![3, 100, 1005, 100, 10, 104, 0, 1105, 1, 12, 104, 1, 99, 7, 8, 2105, -3];
+        assert_eq!(assemble(&disassemble(&program)), Ok(program));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |line: usize, message: &str| Err(AssemblerError { line, message: message.to_string() });
+
+        assert_eq!(assemble("HALT\nJUMP #1"), error(2, "Unknown mnemonic JUMP"));
+        assert_eq!(assemble("ADD #1, #2"), error(1, "ADD takes 3 parameters, found 2"));
+        assert_eq!(assemble("IN -> #4"), error(1, "IN can't write to an immediate value"));
+        assert_eq!(assemble("a: HALT\na: HALT"), error(2, "Label a is defined twice"));
+        assert_eq!(assemble("HALT\n\nOUT [missing]"), error(3, "Unknown label missing"));
+        assert_eq!(assemble("0000: HALT\n0002: HALT"), error(2, "Address 0002 doesn't match the assembled address 1"));
+        assert_eq!(assemble("OUT 5"), error(1, "Operand 5 should be #value, [address] or [rb+offset]"));
+    }
+}
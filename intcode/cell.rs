@@ -0,0 +1,111 @@
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+
+/// Value held by a memory cell of the virtual machine. Arithmetic returns
+/// `None` when the result can't be represented, which stops the machine with
+/// `VmError::Overflow`.
+pub trait Cell: Clone + Default + PartialEq + PartialOrd + fmt::Debug + fmt::Display {
+    fn from_i64(value: i64) -> Self;
+
+    /// The value as `i64`, or `None` when it doesn't fit.
+    fn to_i64(&self) -> Option<i64>;
+
+    fn try_add(&self, other: &Self) -> Option<Self>;
+    fn try_mul(&self, other: &Self) -> Option<Self>;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Plain machine words, which wrap around on overflow.
+impl Cell for i64 {
+    fn from_i64(value: i64) -> i64 {
+        value
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        Some(*self)
+    }
+
+    fn try_add(&self, other: &i64) -> Option<i64> {
+        Some(self.wrapping_add(*other))
+    }
+
+    fn try_mul(&self, other: &i64) -> Option<i64> {
+        Some(self.wrapping_mul(*other))
+    }
+}
+
+/// Machine words which report overflow instead of wrapping around.
+#[derive(PartialEq, PartialOrd, Debug, Default, Clone, Copy)]
+pub struct Checked(pub i64);
+
+impl fmt::Display for Checked {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Cell for Checked {
+    fn from_i64(value: i64) -> Checked {
+        Checked(value)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        Some(self.0)
+    }
+
+    fn try_add(&self, other: &Checked) -> Option<Checked> {
+        self.0.checked_add(other.0).map(Checked)
+    }
+
+    fn try_mul(&self, other: &Checked) -> Option<Checked> {
+        self.0.checked_mul(other.0).map(Checked)
+    }
+}
+
+/// Arbitrary precision integers, which never overflow.
+impl Cell for BigInt {
+    fn from_i64(value: i64) -> BigInt {
+        BigInt::from(value)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        ToPrimitive::to_i64(self)
+    }
+
+    fn try_add(&self, other: &BigInt) -> Option<BigInt> {
+        Some(self + other)
+    }
+
+    fn try_mul(&self, other: &BigInt) -> Option<BigInt> {
+        Some(self * other)
+    }
+}
+
+/// Converts a program read with `get_program` to another cell type.
+pub fn to_cells<C: Cell>(program: &[i64]) -> Vec<C> {
+    program.iter().map(|value| C::from_i64(*value)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_overflow() {
+        assert_eq!(i64::MAX.try_add(&1), Some(i64::MIN));
+        assert_eq!(Checked(i64::MAX).try_add(&Checked(1)), None);
+        assert_eq!(Checked(1 << 32).try_mul(&Checked(1 << 31)), None);
+        assert_eq!(Checked(-3).try_mul(&Checked(5)), Some(Checked(-15)));
+
+        let big = BigInt::from(i64::MAX).try_mul(&BigInt::from(4)).unwrap();
+        assert_eq!(big.to_string(), "36893488147419103228");
+        assert_eq!(Cell::to_i64(&big), None);
+        assert_eq!(Cell::to_i64(&BigInt::from(-7)), Some(-7));
+    }
+}
@@ -0,0 +1,234 @@
+use crate::{mnemonic, VmError};
+use crate::{ADD, MULTIPLY, INPUT, OUTPUT, JMP_TRUE, JMP_FALSE, LESS_THAN, EQUALS, ADJUST_BASE};
+
+// Every case starts by setting the relative base, followed by the code under
+// test. Its data is kept at the end of the program.
+const BASE: i64 = 40;
+const FIRST: i64 = 30;
+const SECOND: i64 = 31;
+const DESTINATION: i64 = 32;
+const LENGTH: usize = 33;
+const FAR: i64 = 1000;
+
+const READ_MODES: [i64; 3] = [0, 1, 2];
+const WRITE_MODES: [i64; 2] = [0, 2];
+
+/// Program with its input and the output, or error, it has to end with.
+#[derive(PartialEq, Debug)]
+pub struct ConformanceCase {
+    pub name: String,
+    pub program: Vec<i64>,
+    pub input: Vec<i64>,
+    pub expected: Result<Vec<i64>, VmError>,
+}
+
+fn case(name: String, code: &[i64], data: &[(i64, i64)], input: &[i64], expected: Result<Vec<i64>, VmError>) -> ConformanceCase {
+    let mut program = vec![109, BASE];
+    program.extend(code);
+    program.resize(LENGTH.max(program.len()), 0);
+    for (address, value) in data {
+        program[*address as usize] = *value;
+    }
+
+    ConformanceCase { name, program, input: input.to_vec(), expected }
+}
+
+/// Parameter reading `value`, which is stored at `address` unless it's immediate.
+fn read_parameter(mode: i64, address: i64, value: i64) -> i64 {
+    match mode {
+        0 => address,
+        1 => value,
+        _ => address - BASE,
+    }
+}
+
+fn write_parameter(mode: i64, address: i64) -> i64 {
+    read_parameter(mode, address, 0)
+}
+
+/// Cases for every opcode with all valid combinations of parameter modes,
+/// writing both into the program and past its end, followed by the examples
+/// from the puzzles and all the errors.
+pub fn conformance_cases() -> Vec<ConformanceCase> {
+    let mut cases = Vec::new();
+
+    for opcode in [ADD, MULTIPLY, LESS_THAN, EQUALS] {
+        for (mode1, mode2, mode3) in READ_MODES.iter().flat_map(|m1| READ_MODES.iter().flat_map(move |m2| WRITE_MODES.iter().map(move |m3| (*m1, *m2, *m3)))) {
+            for destination in [DESTINATION, FAR] {
+                for (a, b) in [(7, -3), (-3, 7), (5, 5)] {
+                    let code = opcode + 100 * mode1 + 1000 * mode2 + 10000 * mode3;
+                    let result = match opcode {
+                        ADD => a + b,
+                        MULTIPLY => a * b,
+                        LESS_THAN => (a < b) as i64,
+                        _ => (a == b) as i64,
+                    };
+
+                    cases.push(case(
+                        format!("{} {} with {} and {} into {}", mnemonic(opcode).unwrap(), code, a, b, destination),
+                        &[code, read_parameter(mode1, FIRST, a), read_parameter(mode2, SECOND, b), write_parameter(mode3, destination), 4, destination, 99],
+                        &[(FIRST, a), (SECOND, b)],
+                        &[],
+                        Ok(vec![result])));
+                }
+            }
+        }
+    }
+
+    for mode in WRITE_MODES {
+        for destination in [DESTINATION, FAR] {
+            let code = INPUT + 100 * mode;
+            cases.push(case(format!("IN {} into {}", code, destination),
+                &[code, write_parameter(mode, destination), 4, destination, 99], &[], &[42], Ok(vec![42])));
+        }
+    }
+
+    for mode in READ_MODES {
+        let code = OUTPUT + 100 * mode;
+        cases.push(case(format!("OUT {}", code), &[code, read_parameter(mode, FIRST, 42), 99], &[(FIRST, 42)], &[], Ok(vec![42])));
+    }
+
+    // Jumps over the output of 1 to the output of 2 at 8
+    for opcode in [JMP_TRUE, JMP_FALSE] {
+        for (mode1, mode2) in READ_MODES.iter().flat_map(|m1| READ_MODES.iter().map(move |m2| (*m1, *m2))) {
+            for condition in [0, 5] {
+                let code = opcode + 100 * mode1 + 1000 * mode2;
+                let taken = (condition != 0) == (opcode == JMP_TRUE);
+                cases.push(case(format!("{} {} with {}", mnemonic(opcode).unwrap(), code, condition),
+                    &[code, read_parameter(mode1, FIRST, condition), read_parameter(mode2, SECOND, 8), 104, 1, 99, 104, 2, 99],
+                    &[(FIRST, condition), (SECOND, 8)],
+                    &[],
+                    Ok(vec![if taken { 2 } else { 1 }])));
+            }
+        }
+    }
+
+    // Moves the relative base by 5 and reads the second value relative to it
+    for mode in READ_MODES {
+        let code = ADJUST_BASE + 100 * mode;
+        cases.push(case(format!("ARB {}", code), &[code, read_parameter(mode, FIRST, 5), 204, SECOND - BASE - 5, 99],
+            &[(FIRST, 5), (SECOND, 77)], &[], Ok(vec![77])));
+    }
+
+    cases.push(case("HALT".to_string(), &[99, 104, 1], &[], &[], Ok(vec![])));
+
+    let quine = vec![109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99];
+    cases.push(ConformanceCase { name: "Quine".to_string(), program: quine.clone(), input: vec![], expected: Ok(quine) });
+    cases.push(ConformanceCase { name: "Large product".to_string(), program: vec![1102,34915192,34915192,7,4,7,99,0], input: vec![],
+        expected: Ok(vec![1219070632396864]) });
+    cases.push(ConformanceCase { name: "Large value".to_string(), program: vec![104,1125899906842624,99], input: vec![],
+        expected: Ok(vec![1125899906842624]) });
+    let compare = vec![3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9];
+    cases.push(ConformanceCase { name: "Jumps with zero input".to_string(), program: compare.clone(), input: vec![0], expected: Ok(vec![0]) });
+    cases.push(ConformanceCase { name: "Jumps with other input".to_string(), program: compare, input: vec![3], expected: Ok(vec![1]) });
+
+    cases.push(case("Unknown opcode".to_string(), &[42], &[], &[], Err(VmError::UnknownOpcode { ip: 2, opcode: 42 })));
+    cases.push(case("Invalid parameter mode".to_string(), &[304, 0, 99], &[], &[], Err(VmError::InvalidParameterMode { ip: 2, mode: 3 })));
+    cases.push(case("Negative address".to_string(), &[4, -1, 99], &[], &[], Err(VmError::NegativeAddress { ip: 2, address: -1 })));
+    cases.push(case("Negative relative address".to_string(), &[204, -BASE - 2, 99], &[], &[], Err(VmError::NegativeAddress { ip: 2, address: -2 })));
+    cases.push(case("Negative jump target".to_string(), &[1105, 1, -5], &[], &[], Err(VmError::NegativeAddress { ip: 2, address: -5 })));
+    cases.push(case("Immediate mode write".to_string(), &[11101, 1, 1, 0, 99], &[], &[], Err(VmError::ImmediateModeWrite { ip: 2 })));
+    cases.push(case("Missing input".to_string(), &[3, FIRST, 3, FIRST, 99], &[], &[7], Err(VmError::MissingInput { ip: 4 })));
+
+    cases
+}
+
+/// Runs all the conformance cases with `run`, which runs a program with the
+/// input until it halts, and returns a description of every case that failed.
+pub fn check_conformance<F: Fn(&[i64], &[i64]) -> Result<Vec<i64>, VmError>>(run: F) -> Vec<String> {
+    conformance_cases().into_iter().filter_map(|case| {
+        let result = run(&case.program, &case.input);
+        if result == case.expected {
+            None
+        }
+        else {
+            Some(format!("{}: expected {:?}, got {:?}", case.name, case.expected, result))
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::mpsc::channel;
+    use crate::{spawn_vm, to_cells, BigInt, Cell, Checked, Vm};
+
+    fn run_default(program: &[i64], input: &[i64]) -> Result<Vec<i64>, VmError> {
+        let mut vm = Vm::new(program.to_vec());
+        vm.push_inputs(input);
+        vm.run_to_end()
+    }
+
+    #[test]
+    fn test_cases() {
+        let cases = conformance_cases();
+        assert_eq!(cases.len(), 432 + 4 + 3 + 36 + 3 + 1 + 5 + 7);
+        assert!(cases.iter().all(|case| case.program.len() >= 3));
+    }
+
+    #[test]
+    fn test_default_vm() {
+        assert_eq!(check_conformance(run_default), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_reports_failures() {
+        let failures = check_conformance(|program, input| run_default(program, input).map(|_| vec![]));
+        assert!(failures.contains(&"OUT 4: expected Ok([42]), got Ok([])".to_string()));
+        assert!(!failures.iter().any(|failure| failure.starts_with("HALT")));
+    }
+
+    #[test]
+    fn test_hash_map_storage() {
+        assert_eq!(check_conformance(|program, input| {
+            let mut vm: Vm<i64, HashMap<usize, i64>> = Vm::from_program(program.to_vec());
+            vm.push_inputs(input);
+            vm.run_to_end()
+        }), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_uncached_vm() {
+        assert_eq!(check_conformance(|program, input| {
+            let mut vm = Vm::new(program.to_vec());
+            vm.memory.disable_instruction_cache();
+            vm.push_inputs(input);
+            vm.run_to_end()
+        }), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_checked_vm() {
+        assert_eq!(check_conformance(|program, input| {
+            let mut vm: Vm<Checked> = Vm::from_program(to_cells(program));
+            vm.push_inputs(&to_cells(input));
+            vm.run_to_end().map(|output| output.iter().map(|value| value.0).collect())
+        }), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_big_int_vm() {
+        assert_eq!(check_conformance(|program, input| {
+            let mut vm: Vm<BigInt> = Vm::from_program(to_cells(program));
+            vm.push_inputs(&to_cells(input));
+            vm.run_to_end().map(|output| output.iter().map(|value| Cell::to_i64(value).unwrap()).collect())
+        }), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_threaded_vm() {
+        assert_eq!(check_conformance(|program, input| {
+            let (sender, receiver) = channel();
+            let (output_sender, output) = channel();
+            for value in input {
+                sender.send(*value).unwrap();
+            }
+            drop(sender);
+
+            spawn_vm(Vm::new(program.to_vec()), receiver, output_sender).join().unwrap()?;
+            Ok(output.iter().collect())
+        }), Vec::<String>::new());
+    }
+}
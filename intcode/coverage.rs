@@ -0,0 +1,237 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::{reachable, Cell, ExecutionEvent, Observer, Symbols};
+use crate::disassembler::listing;
+use crate::{JMP_TRUE, JMP_FALSE};
+
+const COUNT_WIDTH: usize = 8;
+
+/// Observer recording how many times every instruction was executed and
+/// which way the jumps went. Coverage of separate runs can be merged, and is
+/// kept as text with a line per executed address, followed by the number of
+/// times it was executed and, for jumps, how many times they were taken and
+/// not taken:
+///
+/// ```text
+/// 0 1
+/// 2 5 4 1
+/// ```
+#[derive(PartialEq, Debug, Default, Clone)]
+pub struct Coverage {
+    executed: BTreeMap<usize, u64>,
+    branches: BTreeMap<usize, (u64, u64)>,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    /// Number of times the instruction at the address was executed.
+    pub fn count(&self, address: usize) -> u64 {
+        self.executed.get(&address).cloned().unwrap_or(0)
+    }
+
+    /// Number of times the jump at the address was taken and not taken.
+    pub fn branch(&self, address: usize) -> (u64, u64) {
+        self.branches.get(&address).cloned().unwrap_or((0, 0))
+    }
+
+    pub fn executed(&self) -> BTreeSet<usize> {
+        self.executed.keys().cloned().collect()
+    }
+
+    /// Adds the coverage of another run.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (address, count) in &other.executed {
+            *self.executed.entry(*address).or_insert(0) += count;
+        }
+        for (address, (taken, not_taken)) in &other.branches {
+            let branch = self.branches.entry(*address).or_insert((0, 0));
+            branch.0 += taken;
+            branch.1 += not_taken;
+        }
+    }
+
+    /// Listing of the program with the number of times every instruction was
+    /// executed, or `-` for the ones that never were, and how many times every
+    /// conditional jump was taken. Addresses that were executed are listed as
+    /// instructions even when the disassembler can't reach them.
+    pub fn annotate(&self, program: &[i64], symbols: &Symbols) -> String {
+        let mut annotated = String::new();
+        for (instruction, line) in self.listing(program, symbols) {
+            let (count, branch) = match instruction {
+                Some((address, conditional)) => {
+                    let count = match self.count(address) {
+                        0 => "-".to_string(),
+                        count => count.to_string(),
+                    };
+                    let (taken, not_taken) = self.branch(address);
+                    let branch = match conditional {
+                        true => format!("  ; taken {}, not taken {}", taken, not_taken),
+                        false => String::new(),
+                    };
+                    (count, branch)
+                },
+                None => (String::new(), String::new()),
+            };
+            annotated += &format!("{:>width$} {}{}\n", count, line, branch, width = COUNT_WIDTH);
+        }
+
+        annotated
+    }
+
+    /// How much of the program was executed, like
+    /// `Executed 120 of 300 instructions (40.0%), 30 of 80 branch directions (37.5%)`.
+    /// Code only reached through jumps to computed addresses that were never
+    /// taken isn't known, so it isn't counted.
+    pub fn summary(&self, program: &[i64]) -> String {
+        let (mut instructions, mut executed, mut directions, mut covered) = (0, 0, 0, 0);
+        for (address, conditional) in self.listing(program, &Symbols::new()).into_iter().filter_map(|(instruction, _)| instruction) {
+            instructions += 1;
+            if self.count(address) > 0 {
+                executed += 1;
+            }
+            if conditional {
+                let (taken, not_taken) = self.branch(address);
+                directions += 2;
+                covered += (taken > 0) as usize + (not_taken > 0) as usize;
+            }
+        }
+
+        let percent = |part: usize, total: usize| if total == 0 { 100.0 } else { 100.0 * part as f64 / total as f64 };
+        format!("Executed {} of {} instructions ({:.1}%), {} of {} branch directions ({:.1}%)",
+            executed, instructions, percent(executed, instructions), covered, directions, percent(covered, directions))
+    }
+
+    /// Lines of the listing, with the address of the instruction on each of
+    /// them and whether it's a conditional jump.
+    fn listing(&self, program: &[i64], symbols: &Symbols) -> Vec<(Option<(usize, bool)>, String)> {
+        let mut instructions = reachable(program);
+        instructions.extend(self.executed.keys());
+
+        listing(program, &instructions, symbols).into_iter().map(|(instruction, line)| {
+            let instruction = instruction.map(|instruction| {
+                let jump = instruction.opcode == JMP_TRUE || instruction.opcode == JMP_FALSE;
+                let conditional = jump && !instruction.is_unconditional_jump() && !instruction.is_never_taken_jump();
+                (instruction.address, conditional)
+            });
+            (instruction, line)
+        }).collect()
+    }
+
+    pub fn parse(text: &str) -> io::Result<Coverage> {
+        let mut coverage = Coverage::new();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Expected address and counts, found {}", line));
+
+            let numbers: Vec<u64> = line.split_whitespace().map(|word| word.parse()).collect::<Result<_, _>>().map_err(|_| invalid())?;
+            match numbers.as_slice() {
+                [address, count] => {
+                    coverage.executed.insert(*address as usize, *count);
+                },
+                [address, count, taken, not_taken] => {
+                    coverage.executed.insert(*address as usize, *count);
+                    coverage.branches.insert(*address as usize, (*taken, *not_taken));
+                },
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(coverage)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Coverage> {
+        Coverage::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (address, count) in &self.executed {
+            match self.branches.get(address) {
+                Some((taken, not_taken)) => writeln!(f, "{} {} {} {}", address, count, taken, not_taken)?,
+                None => writeln!(f, "{} {}", address, count)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<C: Cell> Observer<C> for Coverage {
+    fn observe(&mut self, event: &ExecutionEvent<C>) {
+        *self.executed.entry(event.ip).or_insert(0) += 1;
+
+        if event.opcode == JMP_TRUE || event.opcode == JMP_FALSE {
+            let branch = self.branches.entry(event.ip).or_insert((0, 0));
+            if event.parameters[0].is_zero() != (event.opcode == JMP_TRUE) {
+                branch.0 += 1;
+            }
+            else {
+                branch.1 += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{assemble, Vm};
+
+    fn run(program: &[i64], input: i64) -> Coverage {
+        let mut vm = Vm::new(program.to_vec());
+        vm.push_input(input);
+        let mut coverage = Coverage::new();
+        vm.run_until_input_observed(&mut coverage).unwrap();
+        coverage
+    }
+
+    #[test]
+    fn test_coverage() {
+        let program = assemble("
+                    IN -> [value]
+                    JF [value], #zero
+                    OUT #1
+                    JT #1, [exit]
+            zero:   OUT #0
+            end:    HALT
+            value:  DATA 0
+            exit:   DATA end
+        ").unwrap();
+
+        let mut coverage = run(&program, 5);
+        assert_eq!(coverage.count(0), 1);
+        assert_eq!(coverage.branch(2), (0, 1));
+        assert_eq!(coverage.summary(&program), "Executed 5 of 6 instructions (83.3%), 1 of 2 branch directions (50.0%)");
+
+        coverage.merge(&run(&program, 0));
+        assert_eq!(Coverage::parse(&coverage.to_string()).unwrap(), coverage);
+        assert_eq!(coverage.to_string(), "0 2\n2 2 1 1\n5 1\n7 1 1 0\n10 1\n12 2\n");
+
+        let mut symbols = Symbols::new();
+        symbols.add(10..11, "zero");
+        assert_eq!(coverage.annotate(&program, &symbols).lines().collect::<Vec<&str>>(), vec![
+            "       2 0000: IN -> [13]",
+            "       2 0002: JF [13], #zero  ; taken 1, not taken 1",
+            "       1 0005: OUT #1",
+            "       1 0007: JT #1, [14]",
+            "         zero:",
+            "       1 0010: OUT #0",
+            "       2 0012: HALT",
+            "         0013: DATA 0, 12",
+        ]);
+        assert_eq!(Coverage::new().summary(&program), "Executed 0 of 6 instructions (0.0%), 0 of 2 branch directions (0.0%)");
+        assert!(Coverage::parse("1 2 3\n").is_err());
+    }
+}
@@ -0,0 +1,36 @@
+extern crate intcode;
+
+use std::env;
+use std::fs::read;
+use std::path::Path;
+use intcode::{get_program, Coverage, Symbols};
+
+fn main() {
+
+    let mut arguments: Vec<String> = env::args().skip(1).collect();
+
+    let symbols = Symbols::from_args(&mut arguments);
+
+    if arguments.len() < 2 {
+        panic!("Usage: coverage [--symbols <file>] <program file> <coverage file>...");
+    }
+
+    let raw_input = match read(&arguments[0]) {
+        Err(_) => panic!("Can't read {}!", arguments[0]),
+        Ok(file) => file,
+    };
+
+    // Coverage of all the runs is merged
+    let mut coverage = Coverage::new();
+    for path in &arguments[1..] {
+        match Coverage::load(Path::new(path)) {
+            Ok(run) => coverage.merge(&run),
+            Err(error) => panic!("Can't read coverage from {}: {}", path, error),
+        }
+    }
+
+    let input_string = String::from_utf8_lossy(&raw_input);
+    let program = get_program(input_string.to_string());
+    print!("{}", coverage.annotate(&program, &symbols));
+    println!("\n{}", coverage.summary(&program));
+}
@@ -0,0 +1,31 @@
+extern crate intcode;
+
+use std::env;
+use std::fs::read;
+use std::io;
+use intcode::{get_program, Debugger, Symbols, Vm};
+
+fn main() {
+
+    let mut arguments: Vec<String> = env::args().skip(1).collect();
+
+    let symbols = Symbols::from_args(&mut arguments);
+
+    let path = match arguments.first() {
+        Some(path) => path,
+        None => panic!("Usage: debug [--symbols <file>] <program file>"),
+    };
+
+    let raw_input = match read(path) {
+        Err(_) => panic!("Can't read {}!", path),
+        Ok(file) => file,
+    };
+
+    let input_string = String::from_utf8_lossy(&raw_input);
+    let mut debugger = Debugger::new(Vm::new(get_program(input_string.to_string()))).with_symbols(symbols);
+
+    let stdin = io::stdin();
+    if let Err(error) = debugger.repl(stdin.lock(), io::stdout()) {
+        panic!("{}", error);
+    }
+}
@@ -0,0 +1,448 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::io::{self, BufRead, Write};
+
+use crate::{format_hit, Symbols, Vm, StepResult, VmError, WatchHit, Watchpoint};
+use crate::replay::{step_recorded, Record};
+
+/// Number of executed instructions that can be undone.
+const HISTORY_LIMIT: usize = 1 << 20;
+
+/// Number of values the memory command prints at most.
+const MEMORY_LIMIT: usize = 1 << 16;
+
+/// Reason for the debugger to give control back to the user.
+#[derive(PartialEq, Debug)]
+pub enum Stop {
+    Breakpoint(usize),
+    NeedInput,
+    Halted,
+    OutOfFuel,
+    /// Watched memory was accessed by the instruction before the ip.
+    Watchpoint(Vec<WatchHit>),
+}
+
+/// Debugger wrapping the virtual machine, which stops on breakpoints and
+/// watched memory accesses, and records the last executed instructions, so
+/// they can be undone.
+pub struct Debugger {
+    pub vm: Vm,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: Vec<Watchpoint>,
+    symbols: Symbols,
+    history: VecDeque<Record>,
+}
+
+const HELP: &str = "\
+Commands:
+  break <address>       set a breakpoint
+  delete <address>      remove a breakpoint
+  breakpoints           list breakpoints
+  watch <start> [len]   stop when memory is written
+  rwatch <start> [len]  stop when memory is read
+  awatch <start> [len]  stop when memory is read or written
+  unwatch <start>       remove a watchpoint
+  watchpoints           list watchpoints
+  step [count]          execute instructions one by one
+  back [count]          undo instructions one by one
+  continue              run until a breakpoint, missing input or halt
+  input <value>...      queue input values
+  memory <start> [len]  print memory
+  info                  print ip, relative base and pending input
+  quit                  leave the debugger
+";
+
+impl Debugger {
+    pub fn new(vm: Vm) -> Debugger {
+        Debugger { vm, breakpoints: BTreeSet::new(), watchpoints: Vec::new(), symbols: Symbols::new(), history: VecDeque::new() }
+    }
+
+    /// Names addresses when reporting watched accesses.
+    pub fn with_symbols(mut self, symbols: Symbols) -> Debugger {
+        self.symbols = symbols;
+        self
+    }
+
+    pub fn add_breakpoint(&mut self, address: usize) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<usize> {
+        &self.breakpoints
+    }
+
+    /// Replaces any watchpoint starting at the same address.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.remove_watchpoint(watchpoint.range.start);
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn remove_watchpoint(&mut self, start: usize) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|watchpoint| watchpoint.range.start != start);
+        self.watchpoints.len() != count
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Executes a single instruction.
+    pub fn step(&mut self) -> Result<Option<StepResult>, VmError> {
+        self.step_watched().map(|(result, _)| result)
+    }
+
+    /// Executes a single instruction, returning its accesses to watched memory.
+    fn step_watched(&mut self) -> Result<(Option<StepResult>, Vec<WatchHit>), VmError> {
+        let (result, record) = step_recorded(&mut self.vm)?;
+        let mut hits = Vec::new();
+        if let Some(record) = record {
+            hits = self.watchpoints.iter().flat_map(|watchpoint| watchpoint.hits(&record.event)).collect();
+            if self.history.len() == HISTORY_LIMIT {
+                self.history.pop_front();
+            }
+            self.history.push_back(record);
+        }
+
+        Ok((result, hits))
+    }
+
+    /// Undoes the last executed instruction, queuing its input again.
+    /// Returns false when there's nothing left to undo.
+    pub fn step_back(&mut self) -> bool {
+        match self.history.pop_back() {
+            Some(record) => {
+                record.undo(&mut self.vm);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Runs until the next breakpoint is reached, watched memory is accessed,
+    /// the program needs more input than queued or halts. The instruction at
+    /// the current ip is always executed, so running continues past the
+    /// breakpoint it stopped on.
+    pub fn resume(&mut self) -> Result<(Vec<i64>, Stop), VmError> {
+        let mut output = Vec::new();
+        loop {
+            let (result, hits) = self.step_watched()?;
+            match result {
+                Some(StepResult::Output(value)) => output.push(value),
+                Some(StepResult::NeedInput) => return Ok((output, Stop::NeedInput)),
+                Some(StepResult::Halted) => return Ok((output, Stop::Halted)),
+                Some(StepResult::OutOfFuel { .. }) => return Ok((output, Stop::OutOfFuel)),
+                None => {},
+            }
+
+            if !hits.is_empty() {
+                return Ok((output, Stop::Watchpoint(hits)));
+            }
+
+            if self.breakpoints.contains(&self.vm.ip()) {
+                return Ok((output, Stop::Breakpoint(self.vm.ip())));
+            }
+        }
+    }
+
+    /// Reads commands from `input` until it ends or `quit` is entered.
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        write!(output, "({}) ", self.vm.ip())?;
+        output.flush()?;
+
+        for line in input.lines() {
+            let line = line?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() {
+                write!(output, "({}) ", self.vm.ip())?;
+                output.flush()?;
+                continue;
+            }
+
+            let arguments: Result<Vec<i64>, _> = words[1..].iter().map(|word| word.parse::<i64>()).collect();
+            let arguments = match arguments {
+                Ok(arguments) => arguments,
+                Err(_) => {
+                    write!(output, "Arguments must be numbers\n({}) ", self.vm.ip())?;
+                    output.flush()?;
+                    continue;
+                },
+            };
+
+            match (words[0], arguments.as_slice()) {
+                ("quit", []) | ("q", []) => return Ok(()),
+                ("help", []) | ("h", []) => write!(output, "{}", HELP)?,
+                ("break", [address]) | ("b", [address]) if *address >= 0 => {
+                    self.add_breakpoint(*address as usize);
+                    writeln!(output, "Breakpoint at {}", address)?;
+                },
+                ("delete", [address]) | ("d", [address]) if *address >= 0 => {
+                    if !self.remove_breakpoint(*address as usize) {
+                        writeln!(output, "No breakpoint at {}", address)?;
+                    }
+                },
+                ("breakpoints", []) => {
+                    for address in &self.breakpoints {
+                        writeln!(output, "{}", address)?;
+                    }
+                },
+                ("watch", [start]) | ("rwatch", [start]) | ("awatch", [start]) if *start >= 0 => {
+                    self.report_watch(&mut output, words[0], *start as usize, 1)?
+                },
+                ("watch", [start, length]) | ("rwatch", [start, length]) | ("awatch", [start, length]) if *start >= 0 && *length > 0 => {
+                    self.report_watch(&mut output, words[0], *start as usize, *length as usize)?
+                },
+                ("unwatch", [start]) if *start >= 0 => {
+                    if !self.remove_watchpoint(*start as usize) {
+                        writeln!(output, "No watchpoint at {}", start)?;
+                    }
+                },
+                ("watchpoints", []) => {
+                    for watchpoint in &self.watchpoints {
+                        writeln!(output, "{}", self.describe_watchpoint(watchpoint))?;
+                    }
+                },
+                ("step", []) | ("s", []) => self.report_step(&mut output, 1)?,
+                ("step", [count]) | ("s", [count]) if *count > 0 => self.report_step(&mut output, *count)?,
+                ("back", []) => self.report_back(&mut output, 1)?,
+                ("back", [count]) if *count > 0 => self.report_back(&mut output, *count)?,
+                ("continue", []) | ("c", []) => match self.resume() {
+                    Ok((values, stop)) => {
+                        for value in values {
+                            writeln!(output, "Output: {}", value)?;
+                        }
+                        match stop {
+                            Stop::Breakpoint(address) => writeln!(output, "Breakpoint at {}", address)?,
+                            Stop::NeedInput => writeln!(output, "Waiting for input")?,
+                            Stop::Halted => writeln!(output, "Halted")?,
+                            Stop::OutOfFuel => writeln!(output, "Out of fuel")?,
+                            Stop::Watchpoint(hits) => {
+                                for hit in hits {
+                                    writeln!(output, "Watchpoint: {}", format_hit(&hit, &self.symbols))?;
+                                }
+                            },
+                        }
+                    },
+                    Err(error) => writeln!(output, "Error: {}", error)?,
+                },
+                ("input", values) | ("i", values) if !values.is_empty() => self.vm.push_inputs(values),
+                ("memory", [start]) | ("m", [start]) if *start >= 0 => self.print_memory(&mut output, *start as usize, 1)?,
+                ("memory", [start, length]) | ("m", [start, length]) if *start >= 0 && *length >= 0 => {
+                    if *length as u64 > MEMORY_LIMIT as u64 {
+                        writeln!(output, "Can't print more than {} values at once", MEMORY_LIMIT)?;
+                    }
+                    else {
+                        self.print_memory(&mut output, *start as usize, *length as usize)?
+                    }
+                },
+                ("info", []) => {
+                    writeln!(output, "ip: {}", self.vm.ip())?;
+                    writeln!(output, "relative base: {}", self.vm.memory.relative_base)?;
+                    writeln!(output, "input: {:?}", self.vm.pending_input())?;
+                },
+                _ => writeln!(output, "Unknown command, type help for the list of commands")?,
+            }
+
+            write!(output, "({}) ", self.vm.ip())?;
+            output.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn report_step<W: Write>(&mut self, output: &mut W, count: i64) -> io::Result<()> {
+        for _ in 0..count {
+            let hits = match self.step_watched() {
+                Ok((None, hits)) => hits,
+                Ok((Some(StepResult::Output(value)), hits)) => {
+                    writeln!(output, "Output: {}", value)?;
+                    hits
+                },
+                Ok((Some(StepResult::NeedInput), _)) => return writeln!(output, "Waiting for input"),
+                Ok((Some(StepResult::Halted), _)) => return writeln!(output, "Halted"),
+                Ok((Some(StepResult::OutOfFuel { .. }), _)) => return writeln!(output, "Out of fuel"),
+                Err(error) => return writeln!(output, "Error: {}", error),
+            };
+
+            if !hits.is_empty() {
+                for hit in hits {
+                    writeln!(output, "Watchpoint: {}", format_hit(&hit, &self.symbols))?;
+                }
+                return Ok(());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn report_watch<W: Write>(&mut self, output: &mut W, command: &str, start: usize, length: usize) -> io::Result<()> {
+        let watchpoint = Watchpoint::new(start..start + length, command != "watch", command != "rwatch");
+        writeln!(output, "Watchpoint {}", self.describe_watchpoint(&watchpoint))?;
+        self.add_watchpoint(watchpoint);
+        Ok(())
+    }
+
+    /// Describes the watchpoint like `386 score write` or `10..12 read/write`.
+    fn describe_watchpoint(&self, watchpoint: &Watchpoint) -> String {
+        let range = &watchpoint.range;
+        let mut description = match range.len() {
+            1 => range.start.to_string(),
+            _ => format!("{}..{}", range.start, range.end),
+        };
+        if let Some(name) = self.symbols.name(range.start) {
+            description += &format!(" {}", name);
+        }
+
+        let access = match (watchpoint.reads, watchpoint.writes) {
+            (true, true) => "read/write",
+            (true, false) => "read",
+            _ => "write",
+        };
+        format!("{} {}", description, access)
+    }
+
+    fn report_back<W: Write>(&mut self, output: &mut W, count: i64) -> io::Result<()> {
+        for _ in 0..count {
+            if !self.step_back() {
+                return writeln!(output, "No more history");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn print_memory<W: Write>(&self, output: &mut W, start: usize, length: usize) -> io::Result<()> {
+        let end = start.saturating_add(length);
+        for row in (start..end).step_by(8) {
+            let values: Vec<String> = (row..end.min(row.saturating_add(8))).map(|address| self.vm.memory.read_memory(address).to_string()).collect();
+            writeln!(output, "{:04}: {}", row, values.join(" "))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // Adds every input to a running total, which is printed after each addition.
+    fn sum_program() -> Vec<i64> {
+        vec![3, 11, 1, 11, 12, 12, 4, 12, 1105, 1, 0, 0, 0]
+    }
+
+    #[test]
+    fn test_resume_stops_on_breakpoints() {
+        let mut debugger = Debugger::new(Vm::new(sum_program()));
+        debugger.add_breakpoint(6);
+        debugger.vm.push_inputs(&[2, 3]);
+
+        assert_eq!(debugger.resume(), Ok((vec![], Stop::Breakpoint(6))));
+        assert_eq!(debugger.resume(), Ok((vec![2], Stop::Breakpoint(6))));
+        assert_eq!(debugger.resume(), Ok((vec![5], Stop::NeedInput)));
+
+        assert!(debugger.remove_breakpoint(6));
+        debugger.vm.push_input(4);
+        assert_eq!(debugger.resume(), Ok((vec![9], Stop::NeedInput)));
+    }
+
+    #[test]
+    fn test_step_back() {
+        let mut debugger = Debugger::new(Vm::new(sum_program()));
+        debugger.vm.push_inputs(&[2, 3]);
+        debugger.add_breakpoint(8);
+        assert_eq!(debugger.resume(), Ok((vec![2], Stop::Breakpoint(8))));
+        assert_eq!(debugger.resume(), Ok((vec![5], Stop::Breakpoint(8))));
+
+        let mut output = Vec::new();
+        debugger.repl("back 4\nm 11 2\ninfo\nback 9\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "\
+(8) (8) 0011: 2 2
+(8) ip: 8
+relative base: 0
+input: [3]
+(8) No more history
+(0) ");
+
+        assert_eq!(debugger.resume(), Ok((vec![2], Stop::Breakpoint(8))));
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut symbols = Symbols::new();
+        symbols.add(12..13, "total");
+        let mut debugger = Debugger::new(Vm::new(sum_program())).with_symbols(symbols);
+        debugger.vm.push_inputs(&[2, 3]);
+
+        let mut output = Vec::new();
+        debugger.repl("watch 12
+rwatch 11
+watchpoints
+c
+c
+unwatch 11
+c
+c
+unwatch 11
+".as_bytes(), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "\
+(0) Watchpoint 12 total write
+(0) Watchpoint 11 read
+(0) 12 total write
+11 read
+(0) Watchpoint: 0002 ADD write [12 total]=2
+Watchpoint: 0002 ADD read [11]=2
+(6) Output: 2
+Watchpoint: 0002 ADD write [12 total]=5
+Watchpoint: 0002 ADD read [11]=3
+(6) (6) Output: 5
+Waiting for input
+(0) Waiting for input
+(0) No watchpoint at 11
+(0) ");
+
+        debugger.add_watchpoint(Watchpoint::new(0..13, true, true));
+        debugger.vm.push_input(4);
+        assert_eq!(debugger.resume(), Ok((vec![], Stop::Watchpoint(vec![WatchHit {
+            ip: 0, opcode: 3, address: 11, access: crate::Access::Write, value: 4 }]))));
+        assert_eq!(debugger.watchpoints().len(), 2);
+    }
+
+    #[test]
+    fn test_print_memory() {
+        let mut debugger = Debugger::new(Vm::new(sum_program()));
+        let mut output = Vec::new();
+        debugger.repl("m 3 10\nm 0 9223372036854775807\nm 9223372036854775807 2\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "\
+(0) 0003: 11 12 12 4 12 1105 1 0
+0011: 0 0
+(0) Can't print more than 65536 values at once
+(0) 9223372036854775807: 0 0
+(0) ");
+    }
+
+    #[test]
+    fn test_repl() {
+        let mut debugger = Debugger::new(Vm::new(sum_program()));
+        let commands = "break 6\ninput 7\ncontinue\nstep\nmemory 11 2\ninfo\nc\nfly\nquit\nstep\n";
+        let mut output = Vec::new();
+        debugger.repl(commands.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "\
+(0) Breakpoint at 6
+(0) (0) Breakpoint at 6
+(6) Output: 7
+(8) 0011: 7 7
+(8) ip: 8
+relative base: 0
+input: []
+(8) Waiting for input
+(0) Unknown command, type help for the list of commands
+(0) ");
+        assert_eq!(debugger.vm.ip(), 0);
+    }
+}
@@ -0,0 +1,25 @@
+extern crate intcode;
+
+use std::env;
+use std::fs::read;
+use intcode::{get_program, disassemble_with_symbols, Symbols};
+
+fn main() {
+
+    let mut arguments: Vec<String> = env::args().skip(1).collect();
+
+    let symbols = Symbols::from_args(&mut arguments);
+
+    let path = match arguments.first() {
+        Some(path) => path,
+        None => panic!("Usage: disassemble [--symbols <file>] <program file>"),
+    };
+
+    let raw_input = match read(path) {
+        Err(_) => panic!("Can't read {}!", path),
+        Ok(file) => file,
+    };
+
+    let input_string = String::from_utf8_lossy(&raw_input);
+    print!("{}", disassemble_with_symbols(&get_program(input_string.to_string()), &symbols));
+}
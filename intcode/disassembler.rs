@@ -0,0 +1,334 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::{parse_instruction, ParameterMode, Symbols};
+use crate::{ADD, MULTIPLY, INPUT, OUTPUT, JMP_TRUE, JMP_FALSE, LESS_THAN, EQUALS, ADJUST_BASE, FINISH};
+
+const DATA_PER_LINE: usize = 8;
+
+/// Parameter of a decoded instruction, written as `[100]`, `#5` or `[rb+3]`.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Operand {
+    Position(i64),
+    Immediate(i64),
+    Relative(i64),
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Position(address) => write!(f, "[{}]", address),
+            Operand::Immediate(value) => write!(f, "#{}", value),
+            Operand::Relative(offset) if offset < 0 => write!(f, "[rb{}]", offset),
+            Operand::Relative(offset) => write!(f, "[rb+{}]", offset),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct DecodedInstruction {
+    pub address: usize,
+    pub opcode: i64,
+    pub operands: Vec<Operand>,
+}
+
+pub fn mnemonic(opcode: i64) -> Option<&'static str> {
+    match opcode {
+        ADD => Some("ADD"),
+        MULTIPLY => Some("MUL"),
+        INPUT => Some("IN"),
+        OUTPUT => Some("OUT"),
+        JMP_TRUE => Some("JT"),
+        JMP_FALSE => Some("JF"),
+        LESS_THAN => Some("LT"),
+        EQUALS => Some("EQ"),
+        ADJUST_BASE => Some("ARB"),
+        FINISH => Some("HALT"),
+        _ => None,
+    }
+}
+
+/// Number of parameters the instruction with this opcode takes.
+pub fn parameter_count(opcode: i64) -> Option<usize> {
+    match opcode {
+        ADD | MULTIPLY | LESS_THAN | EQUALS => Some(3),
+        JMP_TRUE | JMP_FALSE => Some(2),
+        INPUT | OUTPUT | ADJUST_BASE => Some(1),
+        FINISH => Some(0),
+        _ => None,
+    }
+}
+
+/// Decodes the instruction at `address`, or returns `None` when the value
+/// there isn't a valid instruction or its parameters run past the program.
+pub fn decode(program: &[i64], address: usize) -> Option<DecodedInstruction> {
+    let instruction = parse_instruction(*program.get(address)?);
+    let count = parameter_count(instruction.opcode)?;
+    if program[address] < 0 || address + count >= program.len() {
+        return None;
+    }
+
+    let modes = [instruction.par1mode, instruction.par2mode, instruction.par3mode];
+    let mut operands = Vec::with_capacity(count);
+    for (offset, mode) in modes.iter().take(count).enumerate() {
+        let value = program[address + offset + 1];
+        operands.push(match *mode {
+            ParameterMode::PositionMode => Operand::Position(value),
+            ParameterMode::ImmediateMode => Operand::Immediate(value),
+            ParameterMode::RelativeMode => Operand::Relative(value),
+            ParameterMode::InvalidMode(_) => return None,
+        });
+    }
+
+    // Only the last parameter of these instructions is written to
+    if (instruction.opcode == INPUT || count == 3) && operands.last().is_some_and(is_immediate) {
+        return None;
+    }
+
+    Some(DecodedInstruction { address, opcode: instruction.opcode, operands })
+}
+
+fn is_immediate(operand: &Operand) -> bool {
+    matches!(operand, Operand::Immediate(_))
+}
+
+impl DecodedInstruction {
+    pub fn length(&self) -> usize {
+        self.operands.len() + 1
+    }
+
+    pub fn next_address(&self) -> usize {
+        self.address + self.length()
+    }
+
+    /// Whether the instruction is a jump with an immediate condition, which is
+    /// always taken.
+    pub fn is_unconditional_jump(&self) -> bool {
+        match (self.opcode, self.operands[0]) {
+            (JMP_TRUE, Operand::Immediate(condition)) => condition != 0,
+            (JMP_FALSE, Operand::Immediate(condition)) => condition == 0,
+            _ => false,
+        }
+    }
+
+    /// Whether the instruction is a jump with an immediate condition, which is
+    /// never taken.
+    pub fn is_never_taken_jump(&self) -> bool {
+        match (self.opcode, self.operands[0]) {
+            (JMP_TRUE, Operand::Immediate(condition)) => condition == 0,
+            (JMP_FALSE, Operand::Immediate(condition)) => condition != 0,
+            _ => false,
+        }
+    }
+
+    /// The instruction with named addresses, like `ADD [score], #1 -> [score]`
+    /// or `JT [rb+1], #loop` for jump targets.
+    pub fn to_string_with_symbols(&self, symbols: &Symbols) -> String {
+        let is_jump = self.opcode == JMP_TRUE || self.opcode == JMP_FALSE;
+        let operands: Vec<String> = self.operands.iter().enumerate().map(|(index, operand)| {
+            let name = match *operand {
+                Operand::Position(address) if address >= 0 => symbols.name(address as usize).map(|name| format!("[{}]", name)),
+                Operand::Immediate(target) if is_jump && index == 1 && target >= 0 => symbols.name(target as usize).map(|name| format!("#{}", name)),
+                _ => None,
+            };
+            name.unwrap_or_else(|| operand.to_string())
+        }).collect();
+
+        format_instruction(self.opcode, &operands)
+    }
+}
+
+fn format_instruction(opcode: i64, operands: &[String]) -> String {
+    let mnemonic = mnemonic(opcode).unwrap_or("???");
+    match opcode {
+        ADD | MULTIPLY | LESS_THAN | EQUALS => format!("{} {}, {} -> {}", mnemonic, operands[0], operands[1], operands[2]),
+        INPUT => format!("{} -> {}", mnemonic, operands[0]),
+        FINISH => mnemonic.to_string(),
+        _ => format!("{} {}", mnemonic, operands.join(", ")),
+    }
+}
+
+impl fmt::Display for DecodedInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operands: Vec<String> = self.operands.iter().map(|operand| operand.to_string()).collect();
+        write!(f, "{}", format_instruction(self.opcode, &operands))
+    }
+}
+
+/// Follows the control flow from the start of the program and returns the
+/// addresses of all instructions that can be reached. Jumps to computed
+/// addresses can't be followed, but return addresses of calls, written with
+/// two immediate parameters right before an unconditional jump, are.
+pub fn reachable(program: &[i64]) -> BTreeSet<usize> {
+    let mut instructions = BTreeSet::new();
+    let mut pending = vec![0];
+
+    while let Some(address) = pending.pop() {
+        if instructions.contains(&address) {
+            continue;
+        }
+
+        let instruction = match decode(program, address) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        instructions.insert(address);
+
+        match instruction.opcode {
+            FINISH => {},
+            JMP_TRUE | JMP_FALSE => {
+                if let Operand::Immediate(target) = instruction.operands[1] {
+                    if target >= 0 && !instruction.is_never_taken_jump() {
+                        pending.push(target as usize);
+                    }
+                }
+
+                if !instruction.is_unconditional_jump() {
+                    pending.push(instruction.next_address());
+                }
+                else if let Some(return_address) = call_return_address(program, address) {
+                    pending.push(return_address);
+                }
+            },
+            _ => pending.push(instruction.next_address()),
+        }
+    }
+
+    instructions
+}
+
+pub(crate) fn call_return_address(program: &[i64], jump_address: usize) -> Option<usize> {
+    let previous = decode(program, jump_address.checked_sub(4)?)?;
+    let value = match (previous.opcode, previous.operands.as_slice()) {
+        (ADD, [Operand::Immediate(a), Operand::Immediate(b), _]) => a.checked_add(*b)?,
+        (MULTIPLY, [Operand::Immediate(a), Operand::Immediate(b), _]) => a.checked_mul(*b)?,
+        _ => return None,
+    };
+
+    if value >= 0 && (value as usize) < program.len() {
+        Some(value as usize)
+    }
+    else {
+        None
+    }
+}
+
+/// Returns a listing of the program with one instruction per line, like
+/// `0012: ADD [rb+3], #5 -> [100]`. Values that are never reached as
+/// instructions are listed as `DATA`.
+pub fn disassemble(program: &[i64]) -> String {
+    disassemble_with_symbols(program, &Symbols::new())
+}
+
+/// Like `disassemble`, with a `name:` line before every named address and
+/// the names used in place of the addresses they stand for.
+pub fn disassemble_with_symbols(program: &[i64], symbols: &Symbols) -> String {
+    listing(program, &reachable(program), symbols).into_iter().map(|(_, line)| line + "\n").collect()
+}
+
+/// Lines of the listing, together with the instruction each of them shows.
+/// Values at the addresses in `instructions` are decoded as instructions
+/// when they're valid ones, and no symbol starts in the middle of them, as
+/// the label has to go before a line with its address.
+pub(crate) fn listing(program: &[i64], instructions: &BTreeSet<usize>, symbols: &Symbols) -> Vec<(Option<DecodedInstruction>, String)> {
+    let labels: BTreeSet<usize> = symbols.ranges().into_iter().map(|(range, _)| range.start).collect();
+    let decoded = |address: usize| match instructions.contains(&address) {
+        true => decode(program, address).filter(|instruction| labels.range(address + 1..instruction.next_address()).next().is_none()),
+        false => None,
+    };
+    let mut lines = Vec::new();
+
+    let mut address = 0;
+    while address < program.len() {
+        if labels.contains(&address) {
+            lines.push((None, format!("{}:", symbols.name(address).unwrap())));
+        }
+
+        if let Some(instruction) = decoded(address) {
+            let line = format!("{:04}: {}", address, instruction.to_string_with_symbols(symbols));
+            address = instruction.next_address();
+            lines.push((Some(instruction), line));
+        }
+        else {
+            let mut end = address + 1;
+            while end < program.len() && end - address < DATA_PER_LINE && decoded(end).is_none() && !labels.contains(&end) {
+                end += 1;
+            }
+
+            let values: Vec<String> = program[address..end].iter().map(|value| value.to_string()).collect();
+            lines.push((None, format!("{:04}: DATA {}", address, values.join(", "))));
+            address = end;
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let program = vec![21101, 3, -5, 100, 204, -1, 99];
+        let instruction = decode(&program, 0).unwrap();
+        assert_eq!(instruction.operands, vec![Operand::Immediate(3), Operand::Immediate(-5), Operand::Relative(100)]);
+        assert_eq!(instruction.to_string(), "ADD #3, #-5 -> [rb+100]");
+        assert_eq!(decode(&program, 4).unwrap().to_string(), "OUT [rb-1]");
+        assert_eq!(decode(&program, 6).unwrap().to_string(), "HALT");
+
+        // Unknown opcode, writing in immediate mode and missing parameters
+        assert_eq!(decode(&program, 3), None);
+        assert_eq!(decode(&[11101, 1, 1, 0], 0), None);
+        assert_eq!(decode(&[1, 0, 0], 0), None);
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let program = vec![3, 100, 1005, 100, 10, 104, 0, 1105, 1, 12, 104, 1, 99, 7, 8];
+        assert_eq!(disassemble(&program), "\
+0000: IN -> [100]
+0002: JT [100], #10
+0005: OUT #0
+0007: JT #1, #12
+0010: OUT #1
+0012: HALT
+0013: DATA 7, 8
+");
+    }
+
+    #[test]
+    fn test_disassemble_with_symbols() {
+        let program = vec![3, 100, 1005, 100, 10, 104, 0, 1105, 1, 12, 104, 1, 99, 7, 8, 9];
+        let symbols = Symbols::parse("100 value\n10 one\n14..16 table\n").unwrap();
+        assert_eq!(disassemble_with_symbols(&program, &symbols), "\
+0000: IN -> [value]
+0002: JT [value], #one
+0005: OUT #0
+0007: JT #1, #12
+one:
+0010: OUT #1
+0012: HALT
+0013: DATA 7
+table:
+0014: DATA 8, 9
+");
+
+        // Instructions with a symbol inside are split up as data
+        let symbols = Symbols::parse("9 target\n").unwrap();
+        let listing = disassemble_with_symbols(&program, &symbols);
+        assert!(listing.contains("\n0007: DATA 1105, 1\ntarget:\n0009: DATA 12\n0010: OUT #1\n"));
+        assert_eq!(crate::assemble(&listing), Ok(program));
+    }
+
+    #[test]
+    fn test_calls_return() {
+        // Calls the function at 11 with the return address 9 on the stack
+        let program = vec![109, 20, 21101, 9, 0, 0, 1105, 1, 11, 99, 99, 104, 5, 2106, 0, 0];
+        let instructions = reachable(&program);
+        assert!(instructions.contains(&9));
+        assert!(instructions.contains(&13));
+        assert!(!instructions.contains(&10));
+    }
+}
@@ -0,0 +1,64 @@
+extern crate intcode;
+
+use std::env;
+use std::fs::read;
+use intcode::{get_program, PathEnd, SymbolicExecutor};
+
+const DEFAULT_RANGE: (i64, i64) = (-1000, 1000);
+
+fn main() {
+
+    let arguments: Vec<String> = env::args().skip(1).collect();
+    if arguments.len() < 2 || arguments.len() > 3 {
+        panic!("Usage: explore <program file> <number of inputs> [start..end]");
+    }
+
+    let raw_input = match read(&arguments[0]) {
+        Err(_) => panic!("Can't read {}!", arguments[0]),
+        Ok(file) => file,
+    };
+
+    let inputs = match arguments[1].parse::<usize>() {
+        Ok(inputs) => inputs,
+        Err(_) => panic!("Number of inputs {} is not a number", arguments[1]),
+    };
+
+    // Values the inputs can take, with the end excluded
+    let (start, end) = match arguments.get(2) {
+        Some(range) => match range.find("..").map(|dots| (range[..dots].parse::<i64>(), range[dots + 2..].parse::<i64>())) {
+            Some((Ok(start), Ok(end))) => (start, end),
+            _ => panic!("Range {} is not like 0..50", range),
+        },
+        None => DEFAULT_RANGE,
+    };
+
+    let input_string = String::from_utf8_lossy(&raw_input);
+    let executor = SymbolicExecutor::new(get_program(input_string.to_string()), inputs, start..end);
+
+    let exploration = executor.explore();
+    for (index, path) in exploration.paths.iter().enumerate() {
+        let end = match &path.end {
+            PathEnd::Halted => "halted".to_string(),
+            PathEnd::NeedInput => "needs more input".to_string(),
+            PathEnd::SymbolicAddress { ip } => format!("address depends on the input at {}", ip),
+            PathEnd::Error(error) => format!("error: {}", error),
+            PathEnd::StepLimit => "too many steps".to_string(),
+        };
+        println!("Path {}: {}", index + 1, end);
+
+        for constraint in &path.constraints {
+            println!("  if {}", constraint);
+        }
+        for value in &path.output {
+            println!("  output {}", value);
+        }
+        match &path.example {
+            Some(example) => println!("  for example with input {:?}", example),
+            None => println!("  no example input found"),
+        }
+    }
+
+    if exploration.unexplored > 0 {
+        println!("Stopped with {} more paths to explore", exploration.unexplored);
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{decode, reachable, DecodedInstruction, Operand};
+use crate::disassembler::call_return_address;
+use crate::{JMP_TRUE, JMP_FALSE, ADJUST_BASE, FINISH};
+
+/// How control gets from one block to another.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum EdgeKind {
+    /// Continuing with the next instruction.
+    Next,
+    /// A taken jump.
+    Jump,
+    /// A jump which wrote its return address first.
+    Call,
+    /// Where a call returns to.
+    AfterCall,
+}
+
+/// What happens after the last instruction of a block, other than its edges.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum BlockEnd {
+    Continues,
+    Halts,
+    /// Jumps to the return address, after moving the relative base back.
+    Returns,
+    /// Jumps to a computed address, which can't be followed.
+    IndirectJump,
+}
+
+/// Instructions executed one after another, only ever entered at the first one.
+/// Successors without a block of their own aren't valid instructions.
+#[derive(PartialEq, Debug, Clone)]
+pub struct BasicBlock {
+    pub instructions: Vec<DecodedInstruction>,
+    pub successors: Vec<(usize, EdgeKind)>,
+    pub end: BlockEnd,
+}
+
+impl BasicBlock {
+    pub fn start(&self) -> usize {
+        self.instructions[0].address
+    }
+}
+
+/// Basic blocks of all the instructions reachable from the start of a program,
+/// found without running it.
+pub struct ControlFlowGraph {
+    pub blocks: BTreeMap<usize, BasicBlock>,
+}
+
+fn is_jump(instruction: &DecodedInstruction) -> bool {
+    instruction.opcode == JMP_TRUE || instruction.opcode == JMP_FALSE
+}
+
+impl ControlFlowGraph {
+    pub fn new(program: &[i64]) -> ControlFlowGraph {
+        let instructions: Vec<DecodedInstruction> = reachable(program).into_iter()
+            .map(|address| decode(program, address).unwrap())
+            .collect();
+
+        let mut leaders = BTreeSet::new();
+        leaders.insert(0);
+        for instruction in instructions.iter().filter(|instruction| is_jump(instruction)) {
+            if let Operand::Immediate(target) = instruction.operands[1] {
+                if target >= 0 {
+                    leaders.insert(target as usize);
+                }
+            }
+            leaders.insert(instruction.next_address());
+
+            if instruction.is_unconditional_jump() {
+                if let Some(return_address) = call_return_address(program, instruction.address) {
+                    leaders.insert(return_address);
+                }
+            }
+        }
+
+        // Code overlapping other code starts its own block, as does whatever
+        // follows both of them
+        for pair in instructions.windows(2) {
+            if pair[0].next_address() != pair[1].address {
+                leaders.insert(pair[0].next_address());
+                leaders.insert(pair[1].address);
+            }
+        }
+
+        let mut groups: Vec<Vec<DecodedInstruction>> = Vec::new();
+        for instruction in instructions {
+            let continues_group = groups.last().and_then(|group| group.last()).is_some_and(|last| {
+                last.next_address() == instruction.address && !is_jump(last) && last.opcode != FINISH
+            });
+
+            if continues_group && !leaders.contains(&instruction.address) {
+                groups.last_mut().unwrap().push(instruction);
+            }
+            else {
+                groups.push(vec![instruction]);
+            }
+        }
+
+        let blocks = groups.into_iter().map(|instructions| {
+            let (successors, end) = exits(program, &instructions);
+            (instructions[0].address, BasicBlock { instructions, successors, end })
+        }).collect();
+
+        ControlFlowGraph { blocks }
+    }
+
+    /// Edges to addresses which can't be decoded as instructions, as pairs
+    /// of the block they leave and their target.
+    pub fn unresolved_edges(&self) -> Vec<(usize, usize)> {
+        self.blocks.iter()
+            .flat_map(|(start, block)| block.successors.iter().map(move |(target, _)| (*start, *target)))
+            .filter(|(_, target)| !self.blocks.contains_key(target))
+            .collect()
+    }
+
+    /// Addresses of the jumps to computed addresses, other than returns.
+    pub fn indirect_jumps(&self) -> Vec<usize> {
+        self.blocks.values()
+            .filter(|block| block.end == BlockEnd::IndirectJump)
+            .map(|block| block.instructions.last().unwrap().address)
+            .collect()
+    }
+
+    /// The graph in the Graphviz DOT language, with one node per block.
+    /// Blocks ending with an indirect jump, and the targets of unresolved
+    /// edges, are drawn in red.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph program {\n    node [shape=box, fontname=monospace];\n");
+
+        for (start, block) in &self.blocks {
+            let mut label: String = block.instructions.iter()
+                .map(|instruction| format!("{:04}: {}\\l", instruction.address, instruction))
+                .collect();
+
+            let color = match block.end {
+                BlockEnd::Returns => {
+                    label += "return\\l";
+                    ""
+                },
+                BlockEnd::IndirectJump => {
+                    label += "indirect jump\\l";
+                    ", color=red"
+                },
+                _ => "",
+            };
+            dot += &format!("    b{} [label=\"{}\"{}];\n", start, label, color);
+
+            for (target, kind) in &block.successors {
+                let attributes = match kind {
+                    EdgeKind::Next => "",
+                    EdgeKind::Jump => " [label=\"jump\"]",
+                    EdgeKind::Call => " [label=\"call\"]",
+                    EdgeKind::AfterCall => " [style=dashed]",
+                };
+                dot += &format!("    b{} -> b{}{};\n", start, target, attributes);
+            }
+        }
+
+        let unresolved: BTreeSet<usize> = self.unresolved_edges().into_iter().map(|(_, target)| target).collect();
+        for target in unresolved {
+            dot += &format!("    b{} [label=\"{:04}: invalid instruction\\l\", color=red];\n", target, target);
+        }
+
+        dot += "}\n";
+        dot
+    }
+}
+
+fn exits(program: &[i64], instructions: &[DecodedInstruction]) -> (Vec<(usize, EdgeKind)>, BlockEnd) {
+    let last = instructions.last().unwrap();
+    if last.opcode == FINISH {
+        return (Vec::new(), BlockEnd::Halts);
+    }
+    if !is_jump(last) {
+        return (vec![(last.next_address(), EdgeKind::Next)], BlockEnd::Continues);
+    }
+
+    let mut successors = Vec::new();
+    let mut end = BlockEnd::Continues;
+    match last.operands[1] {
+        Operand::Immediate(target) if target >= 0 && !last.is_never_taken_jump() => {
+            match call_return_address(program, last.address) {
+                Some(return_address) if last.is_unconditional_jump() => {
+                    successors.push((target as usize, EdgeKind::Call));
+                    successors.push((return_address, EdgeKind::AfterCall));
+                },
+                _ => successors.push((target as usize, EdgeKind::Jump)),
+            }
+        },
+        Operand::Immediate(_) => {},
+        Operand::Relative(_) if last.is_unconditional_jump() && moves_base_back(instructions) => end = BlockEnd::Returns,
+        _ => end = BlockEnd::IndirectJump,
+    }
+
+    if !last.is_unconditional_jump() {
+        successors.push((last.next_address(), EdgeKind::Next));
+    }
+
+    (successors, end)
+}
+
+fn moves_base_back(instructions: &[DecodedInstruction]) -> bool {
+    match instructions.len().checked_sub(2).map(|index| &instructions[index]) {
+        Some(previous) => previous.opcode == ADJUST_BASE && matches!(previous.operands[0], Operand::Immediate(offset) if offset < 0),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::assemble;
+
+    #[test]
+    fn test_control_flow() {
+        let program = assemble("
+                    ARB #stack
+                    IN -> [value]
+                    JF [value], #skip
+                    ADD #after, #0 -> [rb+0]
+                    JT #1, #double
+            after:  OUT [value]
+            skip:   JT [value], [value]
+                    HALT
+            double: ARB #1
+                    MUL [value], #2 -> [value]
+                    ARB #-1
+                    JT #1, [rb+0]
+            value:  DATA 0
+            stack:  DATA 0
+        ").unwrap();
+
+        let graph = ControlFlowGraph::new(&program);
+        let ends: Vec<(usize, usize, BlockEnd)> = graph.blocks.values()
+            .map(|block| (block.start(), block.instructions.len(), block.end))
+            .collect();
+        assert_eq!(ends, vec![
+            (0, 3, BlockEnd::Continues),
+            (7, 2, BlockEnd::Continues),
+            (14, 1, BlockEnd::Continues),
+            (16, 1, BlockEnd::IndirectJump),
+            (19, 1, BlockEnd::Halts),
+            (20, 4, BlockEnd::Returns),
+        ]);
+
+        assert_eq!(graph.blocks[&0].successors, vec![(16, EdgeKind::Jump), (7, EdgeKind::Next)]);
+        assert_eq!(graph.blocks[&7].successors, vec![(20, EdgeKind::Call), (14, EdgeKind::AfterCall)]);
+        assert_eq!(graph.blocks[&14].successors, vec![(16, EdgeKind::Next)]);
+        assert_eq!(graph.blocks[&16].successors, vec![(19, EdgeKind::Next)]);
+        assert_eq!(graph.indirect_jumps(), vec![16]);
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph program {\n"));
+        assert!(dot.contains("    b16 [label=\"0016: JT [31], [31]\\lindirect jump\\l\", color=red];\n"));
+        assert!(dot.contains("    b7 -> b20 [label=\"call\"];\n    b7 -> b14 [style=dashed];\n"));
+        assert!(dot.ends_with("return\\l\"];\n}\n"));
+        assert_eq!(graph.unresolved_edges(), vec![]);
+    }
+
+    #[test]
+    fn test_return_inside_block() {
+        // The call returns past the instruction after it, which is reached
+        // by the jump over the call
+        let program = assemble("
+                    ARB #stack
+                    IN -> [value]
+                    JT [value], #skip
+                    ADD #back, #0 -> [rb+0]
+                    JT #1, #func
+            skip:   OUT #1
+            back:   OUT #2
+                    HALT
+            func:   ARB #1
+                    ARB #-1
+                    JT #1, [rb+0]
+            value:  DATA 0
+            stack:  DATA 0
+        ").unwrap();
+
+        let graph = ControlFlowGraph::new(&program);
+        let starts: Vec<(usize, usize)> = graph.blocks.values().map(|block| (block.start(), block.instructions.len())).collect();
+        assert_eq!(starts, vec![(0, 3), (7, 2), (14, 1), (16, 2), (19, 3)]);
+        assert_eq!(graph.blocks[&7].successors, vec![(19, EdgeKind::Call), (16, EdgeKind::AfterCall)]);
+        assert_eq!(graph.blocks[&14].successors, vec![(16, EdgeKind::Next)]);
+        assert_eq!(graph.unresolved_edges(), vec![]);
+    }
+
+    #[test]
+    fn test_overlapping_code() {
+        // The jump lands on the second parameter of ADD, which is OUT #20,
+        // and both of them continue with HALT
+        let program = vec![3, 10, 1006, 10, 7, 1101, 1, 104, 20, 99, 0];
+
+        let graph = ControlFlowGraph::new(&program);
+        let ends: Vec<(usize, usize, BlockEnd)> = graph.blocks.values()
+            .map(|block| (block.start(), block.instructions.len(), block.end))
+            .collect();
+        assert_eq!(ends, vec![
+            (0, 2, BlockEnd::Continues),
+            (5, 1, BlockEnd::Continues),
+            (7, 1, BlockEnd::Continues),
+            (9, 1, BlockEnd::Halts),
+        ]);
+        assert_eq!(graph.blocks[&0].successors, vec![(7, EdgeKind::Jump), (5, EdgeKind::Next)]);
+        assert_eq!(graph.blocks[&5].successors, vec![(9, EdgeKind::Next)]);
+        assert_eq!(graph.blocks[&7].successors, vec![(9, EdgeKind::Next)]);
+        assert_eq!(graph.unresolved_edges(), vec![]);
+    }
+
+    #[test]
+    fn test_unresolved_edges() {
+        let graph = ControlFlowGraph::new(&[1005, 5, 4, 99, 42, 0]);
+        assert_eq!(graph.blocks[&0].successors, vec![(4, EdgeKind::Jump), (3, EdgeKind::Next)]);
+        assert_eq!(graph.unresolved_edges(), vec![(0, 4)]);
+        assert!(graph.to_dot().contains("    b0 -> b4 [label=\"jump\"];\n"));
+        assert!(graph.to_dot().ends_with("    b4 [label=\"0004: invalid instruction\\l\", color=red];\n}\n"));
+    }
+}
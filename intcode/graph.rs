@@ -0,0 +1,29 @@
+extern crate intcode;
+
+use std::env;
+use std::fs::read;
+use intcode::{get_program, ControlFlowGraph};
+
+fn main() {
+
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => panic!("Usage: graph <program file>"),
+    };
+
+    let raw_input = match read(&path) {
+        Err(_) => panic!("Can't read {}!", path),
+        Ok(file) => file,
+    };
+
+    let input_string = String::from_utf8_lossy(&raw_input);
+    let graph = ControlFlowGraph::new(&get_program(input_string.to_string()));
+    for address in graph.indirect_jumps() {
+        eprintln!("Indirect jump at {} can't be followed", address);
+    }
+    for (start, target) in graph.unresolved_edges() {
+        eprintln!("Block at {} continues to {}, which isn't a valid instruction", start, target);
+    }
+
+    print!("{}", graph.to_dot());
+}
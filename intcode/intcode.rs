@@ -0,0 +1,175 @@
+//! Intcode virtual machine shared by all the Intcode puzzles.
+
+extern crate num_bigint;
+extern crate num_traits;
+
+use std::convert::From;
+use std::error::Error;
+use std::fmt;
+
+mod ascii;
+mod assembler;
+mod cell;
+mod conformance;
+mod coverage;
+mod debugger;
+mod disassembler;
+mod flow;
+mod memory;
+mod network;
+mod output;
+mod patch;
+mod profiler;
+mod replay;
+mod snapshot;
+mod storage;
+mod symbolic;
+mod symbols;
+mod threaded;
+mod trace;
+mod vm;
+mod watch;
+
+pub use ascii::{AsciiVm, is_ascii};
+pub use assembler::{AssemblerError, assemble};
+pub use cell::{Cell, Checked, to_cells};
+pub use conformance::{ConformanceCase, conformance_cases, check_conformance};
+pub use coverage::Coverage;
+pub use debugger::{Debugger, Stop};
+pub use disassembler::{Operand, DecodedInstruction, decode, disassemble, disassemble_with_symbols, mnemonic, parameter_count, reachable};
+pub use flow::{ControlFlowGraph, BasicBlock, BlockEnd, EdgeKind};
+pub use memory::Memory;
+pub use network::{Network, NetworkStop, Packet, Destination, Topology, Ring, PacketBus};
+pub use output::{OutputGrouper, group_output};
+pub use patch::{Patch, Patches, PatchError};
+pub use profiler::{Profiler, HotLoop};
+pub use replay::{Recorder, Record, step_recorded};
+pub use storage::{Storage, PagedMemory};
+pub use symbolic::{Expr, Constraint, Solution, solve, PathEnd, SymbolicPath, Exploration, SymbolicExecutor};
+pub use symbols::Symbols;
+pub use threaded::{spawn_vm, spawn_polling_vm};
+pub use trace::{Tracer, format_event, format_event_with_symbols};
+pub use vm::{Vm, StepResult, ExecutionEvent, Observer};
+pub use watch::{Access, Watchpoint, WatchHit, Watcher, format_hit};
+pub use num_bigint::BigInt;
+
+pub const FINISH: i64 = 99;
+pub const ADD: i64 = 1;
+pub const MULTIPLY: i64 = 2;
+pub const INPUT: i64 = 3;
+pub const OUTPUT: i64 = 4;
+pub const JMP_TRUE: i64 = 5;
+pub const JMP_FALSE: i64 = 6;
+pub const LESS_THAN: i64 = 7;
+pub const EQUALS: i64 = 8;
+pub const ADJUST_BASE: i64 = 9;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ParameterMode {
+    PositionMode,
+    ImmediateMode,
+    RelativeMode,
+    InvalidMode(i64),
+}
+
+impl From<i64> for ParameterMode {
+    fn from(number: i64) -> Self {
+        match number {
+            0 => ParameterMode::PositionMode,
+            1 => ParameterMode::ImmediateMode,
+            2 => ParameterMode::RelativeMode,
+            _ => ParameterMode::InvalidMode(number),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Instruction {
+    pub opcode: i64,
+    pub par1mode: ParameterMode,
+    pub par2mode: ParameterMode,
+    pub par3mode: ParameterMode,
+}
+
+/// Reasons for the virtual machine to stop executing a program.
+/// Every error carries the instruction pointer of the faulting instruction.
+#[derive(PartialEq, Debug, Clone)]
+pub enum VmError {
+    UnknownOpcode { ip: usize, opcode: i64 },
+    InvalidParameterMode { ip: usize, mode: i64 },
+    NegativeAddress { ip: usize, address: i64 },
+    ImmediateModeWrite { ip: usize },
+    MissingInput { ip: usize },
+    OutOfFuel { ip: usize },
+    Overflow { ip: usize },
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VmError::UnknownOpcode { ip, opcode } => write!(f, "Unknown opcode {} at {}", opcode, ip),
+            VmError::InvalidParameterMode { ip, mode } => write!(f, "Invalid parameter mode {} at {}", mode, ip),
+            VmError::NegativeAddress { ip, address } => write!(f, "Negative address {} accessed at {}", address, ip),
+            VmError::ImmediateModeWrite { ip } => write!(f, "Write in immediate mode at {}", ip),
+            VmError::MissingInput { ip } => write!(f, "Missing input at {}", ip),
+            VmError::OutOfFuel { ip } => write!(f, "Out of fuel at {}", ip),
+            VmError::Overflow { ip } => write!(f, "Value out of range at {}", ip),
+        }
+    }
+}
+
+impl Error for VmError {}
+
+pub fn parse_instruction(code: i64) -> Instruction {
+    Instruction {
+        opcode: code % 100,
+        par1mode: ParameterMode::from((code / 100) % 10),
+        par2mode: ParameterMode::from((code / 1000) % 10),
+        par3mode: ParameterMode::from((code / 10000) % 10),
+    }
+}
+
+pub fn get_program(input: String) -> Vec<i64> {
+    input.trim().split(',').map(|c| match (*c).parse::<i64>() {
+        Err(_) => panic!("Couldn't parse number {}", c),
+        Ok(num) => num,
+    }).collect()
+}
+
+/// Removes a command line flag together with the value after it, which the
+/// tools use for their optional files.
+pub fn take_flag_value(arguments: &mut Vec<String>, flag: &str) -> Option<String> {
+    match arguments.iter().position(|argument| argument == flag) {
+        Some(index) if index + 1 < arguments.len() => arguments.drain(index..index + 2).nth(1),
+        Some(_) => panic!("Missing file after {}", flag),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_parse_instruction() {
+        let instruction = parse_instruction(21002);
+        assert_eq!(instruction.opcode, MULTIPLY);
+        assert_eq!(instruction.par1mode, ParameterMode::PositionMode);
+        assert_eq!(instruction.par2mode, ParameterMode::ImmediateMode);
+        assert_eq!(instruction.par3mode, ParameterMode::RelativeMode);
+    }
+
+    #[test]
+    fn test_get_program() {
+        assert_eq!(get_program("1,-1,4,99\n".to_string()), vec![1, -1, 4, 99]);
+    }
+
+    #[test]
+    fn test_take_flag_value() {
+        let mut arguments: Vec<String> = ["program.txt", "--folded", "out.folded", "42"].iter().map(|argument| argument.to_string()).collect();
+        assert_eq!(take_flag_value(&mut arguments, "--symbols"), None);
+        assert_eq!(take_flag_value(&mut arguments, "--folded"), Some("out.folded".to_string()));
+        assert_eq!(arguments, vec!["program.txt".to_string(), "42".to_string()]);
+    }
+}
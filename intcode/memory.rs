@@ -0,0 +1,267 @@
+use std::borrow::Cow;
+use std::sync::{Arc, OnceLock};
+
+use crate::{parse_instruction, parameter_count, Cell, ParameterMode, VmError};
+use crate::{ADD, MULTIPLY, INPUT, LESS_THAN, EQUALS};
+use crate::storage::{Storage, PagedMemory};
+
+/// Parameter of a decoded instruction, with its mode already applied.
+#[derive(Clone, Debug)]
+pub(crate) enum Param<C: Cell> {
+    Position(usize),
+    Immediate(C),
+    Relative(C),
+    /// Parameter which can't be used, failing with the error when it is.
+    Invalid(VmError),
+}
+
+impl<C: Cell> Default for Param<C> {
+    fn default() -> Param<C> {
+        Param::Immediate(C::default())
+    }
+}
+
+/// Instruction decoded together with its parameters, so executing it again
+/// doesn't read them from memory. Parameters it doesn't have are immediate
+/// zeros.
+#[derive(Clone, Debug)]
+pub(crate) struct Op<C: Cell> {
+    pub opcode: i64,
+    pub params: [Param<C>; 3],
+}
+
+/// Program the machine runs, followed by `virtual_memory` for all the
+/// addresses past its end. Instructions in the program are decoded the first
+/// time they are executed, and the decoded instructions are shared by all
+/// clones of the memory. The program can only be changed with `write_memory`,
+/// which invalidates the instructions the write overlaps, only for the memory
+/// it was made to.
+#[derive(Clone)]
+pub struct Memory<C: Cell = i64, S: Storage<C> = PagedMemory<C>> {
+    program: Vec<C>,
+    pub virtual_memory: S,
+    pub relative_base: C,
+    ops: Arc<Vec<OnceLock<Op<C>>>>,
+    /// Bit per address of the program, set when the instruction starting
+    /// there was written to. Empty until the first write to the program.
+    modified: Vec<u64>,
+}
+
+/// Number of values in the longest instruction.
+const MAX_LENGTH: usize = 4;
+
+pub(crate) fn to_address<C: Cell>(ip: usize, location: &C) -> Result<usize, VmError> {
+    match location.to_i64() {
+        Some(address) if address < 0 => Err(VmError::NegativeAddress { ip, address }),
+        Some(address) => Ok(address as usize),
+        None => Err(VmError::Overflow { ip }),
+    }
+}
+
+impl Memory {
+    pub fn new(program: Vec<i64>) -> Memory {
+        Memory::with_storage(program)
+    }
+}
+
+impl<C: Cell, S: Storage<C>> Memory<C, S> {
+    pub fn with_storage(program: Vec<C>) -> Memory<C, S> {
+        Memory {
+            ops: Arc::new((0..program.len()).map(|_| OnceLock::new()).collect()),
+            modified: Vec::new(),
+            program,
+            virtual_memory: S::default(),
+            relative_base: C::default(),
+        }
+    }
+
+    pub fn program(&self) -> &[C] {
+        &self.program
+    }
+
+    /// Decodes every instruction again when it's executed, like before the
+    /// cache existed. Used to compare the two.
+    pub fn disable_instruction_cache(&mut self) {
+        self.ops = Arc::new(Vec::new());
+    }
+
+    /// Decoded instruction at the location, which is only decoded again when
+    /// the program was written to there since it was loaded.
+    pub(crate) fn fetch_op(&self, location: usize) -> Result<Cow<'_, Op<C>>, VmError> {
+        let modified = self.modified.get(location / 64).is_some_and(|bits| bits & (1 << (location % 64)) != 0);
+        match self.ops.get(location).and_then(OnceLock::get) {
+            Some(op) if !modified => Ok(Cow::Borrowed(op)),
+            _ => self.decode_and_cache(location, modified).map(Cow::Owned),
+        }
+    }
+
+    fn decode_and_cache(&self, location: usize, modified: bool) -> Result<Op<C>, VmError> {
+        let op = self.decode_op(location)?;
+        if !modified && location + parameter_count(op.opcode).unwrap_or(0) < self.program.len() {
+            if let Some(entry) = self.ops.get(location) {
+                let _ = entry.set(op.clone());
+            }
+        }
+        Ok(op)
+    }
+
+    fn decode_op(&self, location: usize) -> Result<Op<C>, VmError> {
+        let code = match self.read_memory(location).to_i64() {
+            Some(code) => code,
+            None => return Err(VmError::Overflow { ip: location }),
+        };
+        let instruction = parse_instruction(code);
+        let modes = [instruction.par1mode, instruction.par2mode, instruction.par3mode];
+        let destination = match instruction.opcode {
+            ADD | MULTIPLY | LESS_THAN | EQUALS => Some(2),
+            INPUT => Some(0),
+            _ => None,
+        };
+
+        let mut params: [Param<C>; 3] = Default::default();
+        for (index, param) in params.iter_mut().enumerate().take(parameter_count(instruction.opcode).unwrap_or(0)) {
+            let value = self.read_memory(location + index + 1);
+            *param = match modes[index] {
+                ParameterMode::PositionMode => match to_address(location, &value) {
+                    Ok(address) => Param::Position(address),
+                    Err(error) => Param::Invalid(error),
+                },
+                ParameterMode::ImmediateMode if destination == Some(index) => Param::Invalid(VmError::ImmediateModeWrite { ip: location }),
+                ParameterMode::ImmediateMode => Param::Immediate(value),
+                ParameterMode::RelativeMode => Param::Relative(value),
+                ParameterMode::InvalidMode(mode) => Param::Invalid(VmError::InvalidParameterMode { ip: location, mode }),
+            };
+        }
+
+        Ok(Op { opcode: instruction.opcode, params })
+    }
+
+    pub fn read_memory(&self, location: usize) -> C {
+        if location < self.program.len() {
+            self.program[location].clone()
+        }
+        else {
+            self.virtual_memory.read(location)
+        }
+    }
+
+    pub fn write_memory(&mut self, location: usize, value: C) {
+
+        if location < self.program.len() {
+            self.program[location] = value;
+            self.invalidate(location);
+        } else {
+            self.virtual_memory.write(location, value);
+        }
+    }
+
+    /// Marks all the instructions the location could be a part of as modified.
+    fn invalidate(&mut self, location: usize) {
+        if self.modified.is_empty() {
+            self.modified = vec![0; self.program.len() / 64 + 1];
+        }
+        for address in location.saturating_sub(MAX_LENGTH - 1)..=location {
+            self.modified[address / 64] |= 1 << (address % 64);
+        }
+    }
+
+    /// Value of the parameter.
+    pub(crate) fn load(&self, pic: usize, param: &Param<C>) -> Result<C, VmError> {
+        match param {
+            Param::Position(address) => Ok(self.read_memory(*address)),
+            Param::Immediate(value) => Ok(value.clone()),
+            Param::Relative(offset) => Ok(self.read_memory(self.relative_address(pic, offset)?)),
+            Param::Invalid(error) => Err(error.clone()),
+        }
+    }
+
+    /// Address the parameter is written to.
+    pub(crate) fn destination(&self, pic: usize, param: &Param<C>) -> Result<usize, VmError> {
+        match param {
+            Param::Position(address) => Ok(*address),
+            Param::Immediate(_) => Err(VmError::ImmediateModeWrite { ip: pic }),
+            Param::Relative(offset) => self.relative_address(pic, offset),
+            Param::Invalid(error) => Err(error.clone()),
+        }
+    }
+
+    /// Address the parameter is read from, or `None` for immediate parameters.
+    pub(crate) fn param_address(&self, pic: usize, param: &Param<C>) -> Option<usize> {
+        match param {
+            Param::Position(address) => Some(*address),
+            Param::Relative(offset) => self.relative_address(pic, offset).ok(),
+            _ => None,
+        }
+    }
+
+    fn relative_address(&self, pic: usize, offset: &C) -> Result<usize, VmError> {
+        match self.relative_base.try_add(offset) {
+            Some(location) => to_address(pic, &location),
+            None => Err(VmError::Overflow { ip: pic }),
+        }
+    }
+
+    pub(crate) fn load_one_param(&self, pic: usize, op: &Op<C>) -> Result<C, VmError> {
+        self.load(pic, &op.params[0])
+    }
+
+    pub(crate) fn load_two_params(&self, pic: usize, op: &Op<C>) -> Result<(C, C), VmError> {
+        let param1 = self.load(pic, &op.params[0])?;
+        let param2 = self.load(pic, &op.params[1])?;
+
+        Ok((param1, param2))
+    }
+
+    pub(crate) fn load_three_params(&self, pic: usize, op: &Op<C>) -> Result<(C, C, usize), VmError> {
+        let (param1, param2) = self.load_two_params(pic, op)?;
+        let param3 = self.destination(pic, &op.params[2])?;
+
+        Ok((param1, param2, param3))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_virtual_memory() {
+        let mut memory = Memory::new(vec![1, 2, 3]);
+        assert_eq!(memory.read_memory(1000), 0);
+
+        memory.write_memory(1000, 42);
+        memory.write_memory(2, 7);
+        assert_eq!(memory.read_memory(1000), 42);
+        assert_eq!(memory.program(), &[1, 2, 7]);
+    }
+
+    #[test]
+    fn test_decoded_instructions() {
+        let memory = Memory::new(vec![1001, 5, 7, 5, 99, 3]);
+        assert!(matches!(memory.fetch_op(0).unwrap().params, [Param::Position(5), Param::Immediate(7), Param::Position(5)]));
+
+        let mut copy = memory.clone();
+        copy.write_memory(2, 8);
+        copy.write_memory(5, 4);
+        assert!(matches!(copy.fetch_op(0).unwrap().params[1], Param::Immediate(8)));
+        assert!(matches!(memory.fetch_op(0).unwrap().params[1], Param::Immediate(7)));
+
+        // Parameters past the end of the program aren't decoded ahead
+        let memory = Memory::new(vec![1101, 1]);
+        memory.fetch_op(0).unwrap();
+        assert!(memory.ops[0].get().is_none());
+    }
+
+    #[test]
+    fn test_relative_destination() {
+        let mut memory = Memory::new(vec![21101, 1, 1, -2]);
+        let op = memory.fetch_op(0).unwrap().into_owned();
+        memory.relative_base = 10;
+        assert_eq!(memory.destination(0, &op.params[2]), Ok(8));
+
+        memory.relative_base = 1;
+        assert_eq!(memory.destination(0, &op.params[2]),
+            Err(VmError::NegativeAddress { ip: 0, address: -1 }));
+    }
+}
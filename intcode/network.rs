@@ -0,0 +1,373 @@
+use std::fmt;
+
+use crate::{OutputGrouper, StepResult, Vm, VmError};
+
+/// Where a packet goes.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Destination {
+    /// Input of the machine with the index.
+    Machine(usize),
+    /// Kept by the topology, like packets for the NAT, which were sent to
+    /// the address.
+    Topology(i64),
+    /// Sent to an address nothing listens on.
+    Dropped(i64),
+}
+
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Destination::Machine(index) => write!(f, "{}", index),
+            Destination::Topology(address) => write!(f, "{}", address),
+            Destination::Dropped(address) => write!(f, "{} (dropped)", address),
+        }
+    }
+}
+
+/// Values sent to a destination, which are delivered as input when it's a
+/// machine. Packets sent by the topology itself, like the NAT waking up the
+/// network, come from `None`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Packet {
+    pub from: Option<usize>,
+    pub to: Destination,
+    pub values: Vec<i64>,
+}
+
+/// Reason for the network to stop running.
+#[derive(PartialEq, Debug)]
+pub enum NetworkStop {
+    /// Every machine halted.
+    Halted,
+    /// Every machine is waiting for packets and the topology has none to send.
+    Idle,
+    /// The observer asked to stop.
+    Stopped,
+}
+
+/// Decides where the output of the machines in a network goes.
+pub trait Topology {
+    /// Number of consecutive output values that make up one message.
+    fn message_size(&self) -> usize;
+
+    /// Destination of a message sent by the machine `from`, together with
+    /// the values it receives.
+    fn route(&mut self, from: usize, message: &[i64]) -> (Destination, Vec<i64>);
+
+    /// Called when the whole network is idle, with the chance to send a
+    /// packet which wakes it up again.
+    fn wake(&mut self) -> Option<(usize, Vec<i64>)> {
+        None
+    }
+
+    /// Value read by machines which need input while there's none queued, so
+    /// they can keep running. Without it they wait for the next packet.
+    fn no_data(&self) -> Option<i64> {
+        None
+    }
+}
+
+/// Feedback loop where every output value goes to the next machine, and the
+/// output of the last one back to the first.
+pub struct Ring {
+    size: usize,
+}
+
+impl Ring {
+    pub fn new(size: usize) -> Ring {
+        Ring { size }
+    }
+}
+
+impl Topology for Ring {
+    fn message_size(&self) -> usize {
+        1
+    }
+
+    fn route(&mut self, from: usize, message: &[i64]) -> (Destination, Vec<i64>) {
+        (Destination::Machine((from + 1) % self.size), message.to_vec())
+    }
+}
+
+/// Bus of machines sending `(address, x, y)` packets to each other, where the
+/// address is the index of the receiving machine. Machines read -1 when no
+/// packets are waiting for them. Packets to unknown addresses are dropped,
+/// unless they're for the NAT, which keeps the last one and sends it to the
+/// machine 0 once the network is idle.
+pub struct PacketBus {
+    size: usize,
+    nat_address: Option<i64>,
+    nat_packet: Option<(i64, i64)>,
+}
+
+const NO_DATA: i64 = -1;
+
+impl PacketBus {
+    pub fn new(size: usize) -> PacketBus {
+        PacketBus { size, nat_address: None, nat_packet: None }
+    }
+
+    pub fn with_nat(mut self, address: i64) -> PacketBus {
+        self.nat_address = Some(address);
+        self
+    }
+
+    /// Last packet the NAT received.
+    pub fn nat_packet(&self) -> Option<(i64, i64)> {
+        self.nat_packet
+    }
+}
+
+impl Topology for PacketBus {
+    fn message_size(&self) -> usize {
+        3
+    }
+
+    fn route(&mut self, _from: usize, message: &[i64]) -> (Destination, Vec<i64>) {
+        let (address, x, y) = (message[0], message[1], message[2]);
+        if Some(address) == self.nat_address {
+            self.nat_packet = Some((x, y));
+            (Destination::Topology(address), vec![x, y])
+        }
+        else if address >= 0 && (address as usize) < self.size {
+            (Destination::Machine(address as usize), vec![x, y])
+        }
+        else {
+            (Destination::Dropped(address), vec![x, y])
+        }
+    }
+
+    fn wake(&mut self) -> Option<(usize, Vec<i64>)> {
+        self.nat_packet.map(|(x, y)| (0, vec![x, y]))
+    }
+
+    fn no_data(&self) -> Option<i64> {
+        Some(NO_DATA)
+    }
+}
+
+/// Network of machines which run in turns, each until it needs input that
+/// isn't there yet. Their output is delivered to the other machines as
+/// decided by the topology. A machine is idle when it ran without getting
+/// any packets or sending any output, and the whole network is idle once all
+/// of its machines are idle or halted.
+pub struct Network<T: Topology> {
+    pub machines: Vec<Vm>,
+    pub topology: T,
+    messages: Vec<OutputGrouper>,
+    received: Vec<bool>,
+    idle: Vec<bool>,
+    halted: Vec<bool>,
+}
+
+impl<T: Topology> Network<T> {
+    /// Input the machines need to start with, like their addresses, has to be
+    /// queued before.
+    pub fn new(machines: Vec<Vm>, topology: T) -> Network<T> {
+        let size = machines.len();
+        Network {
+            messages: (0..size).map(|_| OutputGrouper::new(topology.message_size())).collect(),
+            // The input queued before counts as received, so every machine
+            // gets to run with it before it can be idle
+            received: vec![true; size],
+            idle: vec![false; size],
+            halted: vec![false; size],
+            machines,
+            topology,
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle.iter().zip(&self.halted).all(|(idle, halted)| *idle || *halted)
+    }
+
+    /// Runs until every machine halted or the network is idle for good.
+    pub fn run(&mut self) -> Result<NetworkStop, VmError> {
+        self.run_observed(|_| true)
+    }
+
+    /// Same as `run`, but every packet is passed to the observer, including
+    /// the ones kept by the topology or dropped. The observer can stop the
+    /// network right after the packet by returning false.
+    pub fn run_observed<F: FnMut(&Packet) -> bool>(&mut self, mut observer: F) -> Result<NetworkStop, VmError> {
+        loop {
+            if self.halted.iter().all(|halted| *halted) {
+                return Ok(NetworkStop::Halted);
+            }
+
+            if self.is_idle() {
+                match self.topology.wake() {
+                    Some((to, values)) => {
+                        if !self.deliver(Packet { from: None, to: Destination::Machine(to), values }, &mut observer) {
+                            return Ok(NetworkStop::Stopped);
+                        }
+                    },
+                    None => return Ok(NetworkStop::Idle),
+                }
+            }
+
+            for index in 0..self.machines.len() {
+                if !self.halted[index] && !self.run_machine(index, &mut observer)? {
+                    return Ok(NetworkStop::Stopped);
+                }
+            }
+        }
+    }
+
+    fn run_machine<F: FnMut(&Packet) -> bool>(&mut self, index: usize, observer: &mut F) -> Result<bool, VmError> {
+        let received = self.received[index];
+        self.received[index] = false;
+
+        let (output, state) = self.machines[index].run_until_input()?;
+        match state {
+            StepResult::NeedInput => {
+                if let Some(value) = self.topology.no_data() {
+                    self.machines[index].push_input(value);
+                }
+                self.idle[index] = !received && output.is_empty();
+            },
+            StepResult::Halted => self.halted[index] = true,
+            StepResult::OutOfFuel { ip } => return Err(VmError::OutOfFuel { ip }),
+            StepResult::Output(_) => unreachable!(),
+        }
+
+        for message in self.messages[index].extend(&output) {
+            let (to, values) = self.topology.route(index, &message);
+            if !self.deliver(Packet { from: Some(index), to, values }, observer) {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Passes the packet to its machine, if it goes to one, and to the observer.
+    fn deliver<F: FnMut(&Packet) -> bool>(&mut self, packet: Packet, observer: &mut F) -> bool {
+        if let Destination::Machine(to) = packet.to {
+            self.machines[to].push_inputs(&packet.values);
+            self.received[to] = true;
+            self.idle[to] = false;
+        }
+
+        observer(&packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::assemble;
+
+    #[test]
+    fn test_ring() {
+        let program = vec![3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,
+            27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5];
+        let amplifiers = [9, 8, 7, 6, 5].iter().map(|phase| {
+            let mut amplifier = Vm::new(program.clone());
+            amplifier.push_input(*phase);
+            amplifier
+        }).collect();
+
+        let mut network = Network::new(amplifiers, Ring::new(5));
+        network.machines[0].push_input(0);
+
+        let mut last_signal = 0;
+        let stop = network.run_observed(|packet| {
+            if packet.from == Some(4) {
+                last_signal = packet.values[0];
+            }
+            true
+        });
+
+        assert_eq!(stop, Ok(NetworkStop::Halted));
+        assert_eq!(last_signal, 139629729);
+    }
+
+    // Machine 0 sends a packet to machine 1, which sends it to the NAT with y
+    // increased by one. Machine 0 does the same with packets it receives.
+    fn bus_machines() -> Vec<Vm> {
+        let program = assemble("
+                    IN -> [address]
+                    JF [address], #send
+            recv:   IN -> [x]
+                    EQ [x], #-1 -> [empty]
+                    JT [empty], #recv
+                    IN -> [y]
+                    ADD [y], #1 -> [y]
+                    OUT #255
+                    OUT [x]
+                    OUT [y]
+                    JT #1, #recv
+            send:   OUT #1
+                    OUT #5
+                    OUT #6
+                    JT #1, #recv
+            address: DATA 0
+            x:      DATA 0
+            y:      DATA 0
+            empty:  DATA 0
+        ").unwrap();
+
+        (0..2).map(|address| {
+            let mut machine = Vm::new(program.clone());
+            machine.push_input(address);
+            machine
+        }).collect()
+    }
+
+    #[test]
+    fn test_packet_bus() {
+        let mut network = Network::new(bus_machines(), PacketBus::new(2).with_nat(255));
+
+        let mut packets = Vec::new();
+        let stop = network.run_observed(|packet| {
+            packets.push(packet.clone());
+            packet.values[1] < 9
+        });
+
+        assert_eq!(stop, Ok(NetworkStop::Stopped));
+        assert_eq!(packets, vec![
+            Packet { from: Some(0), to: Destination::Machine(1), values: vec![5, 6] },
+            Packet { from: Some(1), to: Destination::Topology(255), values: vec![5, 7] },
+            Packet { from: None, to: Destination::Machine(0), values: vec![5, 7] },
+            Packet { from: Some(0), to: Destination::Topology(255), values: vec![5, 8] },
+            Packet { from: None, to: Destination::Machine(0), values: vec![5, 8] },
+            Packet { from: Some(0), to: Destination::Topology(255), values: vec![5, 9] },
+        ]);
+        assert_eq!(network.topology.nat_packet(), Some((5, 9)));
+    }
+
+    #[test]
+    fn test_packet_to_nat() {
+        // The first y sent to the NAT, like in part 1 of day 23
+        let mut network = Network::new(bus_machines(), PacketBus::new(2).with_nat(255));
+        let mut first_y = None;
+        let stop = network.run_observed(|packet| {
+            if packet.to == Destination::Topology(255) {
+                first_y = Some(packet.values[1]);
+            }
+            first_y.is_none()
+        });
+        assert_eq!(stop, Ok(NetworkStop::Stopped));
+        assert_eq!(first_y, Some(7));
+
+        // Without the NAT, the packet is dropped but still observed
+        let mut network = Network::new(bus_machines(), PacketBus::new(2));
+        let mut dropped = Vec::new();
+        assert_eq!(network.run_observed(|packet| {
+            if let Destination::Dropped(address) = packet.to {
+                dropped.push((address, packet.values.clone()));
+            }
+            true
+        }), Ok(NetworkStop::Idle));
+        assert_eq!(dropped, vec![(255, vec![5, 7])]);
+    }
+
+    #[test]
+    fn test_idle_network() {
+        let mut network = Network::new(bus_machines(), PacketBus::new(2));
+        assert_eq!(network.run(), Ok(NetworkStop::Idle));
+        assert!(network.is_idle());
+    }
+}
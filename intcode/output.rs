@@ -0,0 +1,61 @@
+/// Collects output values into groups of a fixed size, like the `(x, y, tile)`
+/// triples of the arcade. Values of an incomplete group are kept until the rest
+/// of the group arrives.
+pub struct OutputGrouper {
+    size: usize,
+    pending: Vec<i64>,
+}
+
+impl OutputGrouper {
+    pub fn new(size: usize) -> OutputGrouper {
+        assert!(size > 0, "Output groups can't be empty");
+        OutputGrouper { size, pending: Vec::with_capacity(size) }
+    }
+
+    /// Adds a value and returns the group it completed, if any.
+    pub fn push(&mut self, value: i64) -> Option<Vec<i64>> {
+        self.pending.push(value);
+
+        if self.pending.len() == self.size {
+            Some(self.pending.drain(..).collect())
+        }
+        else {
+            None
+        }
+    }
+
+    /// Adds all the values and returns every group they completed.
+    pub fn extend(&mut self, values: &[i64]) -> Vec<Vec<i64>> {
+        values.iter().filter_map(|value| self.push(*value)).collect()
+    }
+
+    pub fn pending(&self) -> &[i64] {
+        &self.pending
+    }
+}
+
+/// Splits the output into groups of `size` values. Any trailing values that
+/// don't fill a whole group are left out.
+pub fn group_output(output: &[i64], size: usize) -> Vec<Vec<i64>> {
+    OutputGrouper::new(size).extend(output)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_group_output() {
+        assert_eq!(group_output(&[1, 2, 3, 4, 5, 6, 7], 3), vec![vec![1, 2, 3], vec![4, 5, 6]]);
+    }
+
+    #[test]
+    fn test_groups_across_batches() {
+        let mut grouper = OutputGrouper::new(2);
+        assert_eq!(grouper.extend(&[1, 2, 3]), vec![vec![1, 2]]);
+        assert_eq!(grouper.pending(), &[3]);
+        assert_eq!(grouper.push(4), Some(vec![3, 4]));
+        assert!(grouper.pending().is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// New value for an address, optionally checked against the value it has to
+/// hold before patching.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Patch {
+    pub address: usize,
+    pub value: i64,
+    pub expected: Option<i64>,
+}
+
+/// Reasons for a patch not to apply to a program.
+#[derive(PartialEq, Debug)]
+pub enum PatchError {
+    /// Value found at a patched address instead of the expected one, which
+    /// usually means the patch was written for another program.
+    Mismatch { address: usize, expected: i64, found: i64 },
+    /// Address past the end of the program.
+    OutOfRange { address: usize, length: usize },
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PatchError::Mismatch { address, expected, found } => write!(f, "Expected {} at {} before patching, found {}", expected, address, found),
+            PatchError::OutOfRange { address, length } => write!(f, "Can't patch {}, past the end of the program of length {}", address, length),
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+/// Changes to a program applied when it's loaded, kept as text with one
+/// patch per line:
+///
+/// ```text
+/// ; insert coin
+/// 0=1->2
+/// 1500=7
+/// ```
+///
+/// `0=1->2` writes 2 to address 0, which has to hold 1, while `1500=7` writes
+/// without checking. Everything after `;` is a comment.
+#[derive(PartialEq, Debug, Default, Clone)]
+pub struct Patches {
+    patches: Vec<Patch>,
+}
+
+impl Patches {
+    pub fn new() -> Patches {
+        Patches::default()
+    }
+
+    pub fn add(&mut self, patch: Patch) {
+        self.patches.push(patch);
+    }
+
+    pub fn patches(&self) -> &[Patch] {
+        &self.patches
+    }
+
+    /// Checks the addresses and expected values of all patches, then applies
+    /// them in order. Nothing is written when any of them doesn't match.
+    pub fn apply(&self, program: &mut [i64]) -> Result<(), PatchError> {
+        for patch in &self.patches {
+            let found = match program.get(patch.address) {
+                Some(found) => *found,
+                None => return Err(PatchError::OutOfRange { address: patch.address, length: program.len() }),
+            };
+            match patch.expected {
+                Some(expected) if expected != found => return Err(PatchError::Mismatch { address: patch.address, expected, found }),
+                _ => {},
+            }
+        }
+
+        for patch in &self.patches {
+            program[patch.address] = patch.value;
+        }
+
+        Ok(())
+    }
+
+    pub fn parse(text: &str) -> io::Result<Patches> {
+        let mut patches = Patches::new();
+        for line in text.lines() {
+            let line = match line.find(';') {
+                Some(comment) => &line[..comment],
+                None => line,
+            }.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Expected address=value, found {}", line));
+            let (address, values) = match line.find('=') {
+                Some(equals) => (&line[..equals], &line[equals + 1..]),
+                None => return Err(invalid()),
+            };
+            let (expected, value) = match values.find("->") {
+                Some(arrow) => (Some(values[..arrow].trim().parse().map_err(|_| invalid())?), &values[arrow + 2..]),
+                None => (None, values),
+            };
+
+            patches.add(Patch {
+                address: address.trim().parse().map_err(|_| invalid())?,
+                value: value.trim().parse().map_err(|_| invalid())?,
+                expected,
+            });
+        }
+
+        Ok(patches)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Patches> {
+        Patches::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for Patches {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for patch in &self.patches {
+            match patch.expected {
+                Some(expected) => writeln!(f, "{}={}->{}", patch.address, expected, patch.value)?,
+                None => writeln!(f, "{}={}", patch.address, patch.value)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let patches = Patches::parse("; insert coin\n0=1->2\n 4 = -1 ; last\n").unwrap();
+        assert_eq!(patches.to_string(), "0=1->2\n4=-1\n");
+        assert_eq!(Patches::parse(&patches.to_string()).unwrap(), patches);
+
+        let mut program = vec![1, 0, 0, 0, 99];
+        assert_eq!(patches.apply(&mut program), Ok(()));
+        assert_eq!(program, vec![2, 0, 0, 0, -1]);
+
+        // Applying again doesn't match the expected value, and changes nothing
+        assert_eq!(patches.apply(&mut program), Err(PatchError::Mismatch { address: 0, expected: 1, found: 2 }));
+        assert_eq!(program, vec![2, 0, 0, 0, -1]);
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let mut program = vec![1, 0, 0, 0, 99];
+        for (text, address) in &[("0=2\n5=7\n", 5), ("99999999999999=1\n", 99999999999999), (&format!("{}=1\n", usize::MAX)[..], usize::MAX)] {
+            assert_eq!(Patches::parse(text).unwrap().apply(&mut program), Err(PatchError::OutOfRange { address: *address, length: 5 }));
+        }
+        assert_eq!(program, vec![1, 0, 0, 0, 99]);
+    }
+
+    #[test]
+    fn test_invalid_patches() {
+        assert!(Patches::parse("0 2\n").is_err());
+        assert!(Patches::parse("-1=2\n").is_err());
+        assert!(Patches::parse("0=x\n").is_err());
+        assert!(Patches::parse("0=1->\n").is_err());
+    }
+}
@@ -0,0 +1,47 @@
+extern crate intcode;
+
+use std::env;
+use std::fs::{read, write};
+use intcode::{get_program, take_flag_value, Profiler, Vm};
+
+const TOP: usize = 10;
+
+fn main() {
+
+    let mut arguments: Vec<String> = env::args().skip(1).collect();
+
+    // Folded stacks for flamegraphs are written to the file after --folded
+    let folded_path = take_flag_value(&mut arguments, "--folded");
+
+    if arguments.is_empty() {
+        panic!("Usage: profile [--folded <file>] <program file> [input]...");
+    }
+
+    let raw_input = match read(&arguments[0]) {
+        Err(_) => panic!("Can't read {}!", arguments[0]),
+        Ok(file) => file,
+    };
+
+    let input_string = String::from_utf8_lossy(&raw_input);
+    let mut vm = Vm::new(get_program(input_string.to_string()));
+    for argument in &arguments[1..] {
+        match argument.parse::<i64>() {
+            Ok(value) => vm.push_input(value),
+            Err(_) => panic!("Input {} is not a number", argument),
+        }
+    }
+
+    let mut profiler = Profiler::new();
+    match vm.run_until_input_observed(&mut profiler) {
+        Ok((output, state)) => println!("Stopped with {:?} after output {:?}\n", state, output),
+        Err(error) => println!("Stopped with error: {}\n", error),
+    }
+
+    print!("{}", profiler.report(TOP));
+
+    if let Some(path) = folded_path {
+        if write(&path, profiler.folded()).is_err() {
+            panic!("Can't write {}!", path);
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{mnemonic, parameter_count, Cell, ExecutionEvent, Observer};
+
+/// Loop found through a jump back to an earlier address.
+#[derive(PartialEq, Debug)]
+pub struct HotLoop {
+    pub start: usize,
+    pub end: usize,
+    pub iterations: u64,
+    pub instructions: u64,
+}
+
+/// Observer counting executed instructions per address and per opcode.
+/// Jumps that follow writing their own return address are treated as calls,
+/// which gives the stacks for the folded output.
+#[derive(Default)]
+pub struct Profiler {
+    retired: u64,
+    per_address: HashMap<usize, u64>,
+    per_opcode: BTreeMap<i64, u64>,
+    back_jumps: HashMap<(usize, usize), u64>,
+    last_write: Option<i64>,
+    stack: Vec<(usize, usize)>,
+    stacks: HashMap<Vec<usize>, u64>,
+    /// Instructions executed with the current stack, not yet added to `stacks`.
+    on_stack: u64,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    /// Number of executed instructions.
+    pub fn retired(&self) -> u64 {
+        self.retired
+    }
+
+    pub fn count(&self, address: usize) -> u64 {
+        self.per_address.get(&address).cloned().unwrap_or(0)
+    }
+
+    pub fn opcode_counts(&self) -> &BTreeMap<i64, u64> {
+        &self.per_opcode
+    }
+
+    /// Addresses ordered from the most executed one.
+    pub fn hot_addresses(&self) -> Vec<(usize, u64)> {
+        let mut addresses: Vec<(usize, u64)> = self.per_address.iter().map(|(address, count)| (*address, *count)).collect();
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        addresses
+    }
+
+    /// Loops ordered by the number of instructions executed within them.
+    pub fn hot_loops(&self) -> Vec<HotLoop> {
+        let mut loops: Vec<HotLoop> = self.back_jumps.iter().map(|(&(end, start), &iterations)| HotLoop {
+            start,
+            end,
+            iterations,
+            instructions: (start..=end).map(|address| self.count(address)).sum(),
+        }).collect();
+
+        loops.sort_by(|a, b| b.instructions.cmp(&a.instructions).then(a.start.cmp(&b.start)));
+        loops
+    }
+
+    /// Stacks in the folded format read by flamegraph tools, one per line like
+    /// `main;f1424;f1234 1200`, where frames are named after the call targets.
+    pub fn folded(&self) -> String {
+        let mut stacks = self.stacks.clone();
+        if self.on_stack > 0 {
+            *stacks.entry(self.frames()).or_insert(0) += self.on_stack;
+        }
+
+        let mut lines: Vec<String> = stacks.iter().map(|(stack, count)| {
+            let frames: Vec<String> = stack.iter().map(|target| format!("f{}", target)).collect();
+            format!("main{}{} {}", if frames.is_empty() { "" } else { ";" }, frames.join(";"), count)
+        }).collect();
+
+        lines.sort();
+        lines.iter().map(|line| line.clone() + "\n").collect()
+    }
+
+    fn frames(&self) -> Vec<usize> {
+        self.stack.iter().map(|frame| frame.0).collect()
+    }
+
+    /// Adds the instructions executed with the current stack to the stacks,
+    /// before a call or return changes it.
+    fn flush_stack(&mut self) {
+        if self.on_stack > 0 {
+            *self.stacks.entry(self.frames()).or_insert(0) += self.on_stack;
+            self.on_stack = 0;
+        }
+    }
+
+    /// Report with the totals and the `top` hottest addresses and loops.
+    pub fn report(&self, top: usize) -> String {
+        let mut report = format!("Instructions retired: {}\n\nOpcodes:\n", self.retired);
+        for (opcode, count) in &self.per_opcode {
+            report += &format!("  {:<5} {}\n", mnemonic(*opcode).unwrap_or("???"), count);
+        }
+
+        report += "\nHot addresses:\n";
+        for (address, count) in self.hot_addresses().into_iter().take(top) {
+            report += &format!("  {:04} {}\n", address, count);
+        }
+
+        report += "\nHot loops:\n";
+        for hot_loop in self.hot_loops().into_iter().take(top) {
+            report += &format!("  {:04}-{:04} {} iterations, {} instructions\n",
+                hot_loop.start, hot_loop.end, hot_loop.iterations, hot_loop.instructions);
+        }
+
+        report
+    }
+}
+
+impl<C: Cell> Observer<C> for Profiler {
+    fn observe(&mut self, event: &ExecutionEvent<C>) {
+        self.retired += 1;
+        *self.per_address.entry(event.ip).or_insert(0) += 1;
+        *self.per_opcode.entry(event.opcode).or_insert(0) += 1;
+
+        self.on_stack += 1;
+
+        let next_address = event.ip + parameter_count(event.opcode).unwrap_or(0) + 1;
+        let jumped = event.next_ip != next_address && event.next_ip != event.ip;
+        if jumped {
+            if self.last_write == Some(next_address as i64) {
+                self.flush_stack();
+                self.stack.push((event.next_ip, next_address));
+            }
+            else if let Some(frame) = self.stack.iter().rposition(|frame| frame.1 == event.next_ip) {
+                self.flush_stack();
+                self.stack.truncate(frame);
+            }
+            else if event.next_ip <= event.ip {
+                *self.back_jumps.entry((event.ip, event.next_ip)).or_insert(0) += 1;
+            }
+        }
+
+        self.last_write = event.write.as_ref().and_then(|(_, value)| value.to_i64());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{assemble, Vm, StepResult};
+
+    #[test]
+    fn test_profile() {
+        let program = assemble("
+                    ARB #stack
+                    ADD #3, #0 -> [counter]
+            loop:   ADD #after, #0 -> [rb]
+                    JT #1, #twice
+            after:  ADD [counter], #-1 -> [counter]
+                    JT [counter], #loop
+                    HALT
+            twice:  MUL [rb+1], #2 -> [rb+1]
+                    JT #1, [rb]
+            counter: DATA 0
+            stack:  DATA 0, 1
+        ").unwrap();
+
+        let mut vm = Vm::new(program);
+        let mut profiler = Profiler::new();
+        assert_eq!(vm.run_observed(&mut profiler), Ok(StepResult::Halted));
+
+        assert_eq!(profiler.retired(), 21);
+        assert_eq!(profiler.opcode_counts().get(&2), Some(&3));
+        assert_eq!(profiler.count(6), 3);
+        assert_eq!(profiler.hot_addresses()[0], (6, 3));
+        assert_eq!(profiler.hot_loops(), vec![HotLoop { start: 6, end: 17, iterations: 2, instructions: 12 }]);
+        assert_eq!(profiler.folded(), "main 15\nmain;f21 6\n");
+    }
+}
@@ -0,0 +1,203 @@
+use crate::{ExecutionEvent, StepResult, Vm, VmError};
+use crate::{ADD, MULTIPLY, INPUT, LESS_THAN, EQUALS, FINISH};
+
+/// Executed instruction together with what it changed, which is enough to
+/// undo it.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Record {
+    pub event: ExecutionEvent,
+    /// Relative base before the instruction.
+    pub relative_base: i64,
+    /// Address the instruction wrote to, with the value it held before.
+    pub overwritten: Option<(usize, i64)>,
+}
+
+impl Record {
+    /// Puts the machine back into the state before the instruction was
+    /// executed, with its input queued again. Records have to be undone
+    /// starting with the last one.
+    pub fn undo(&self, vm: &mut Vm) {
+        if let Some((address, value)) = self.overwritten {
+            vm.memory.write_memory(address, value);
+        }
+        if let Some(value) = self.event.input {
+            vm.input.push_front(value);
+        }
+        if let (Some(fuel), false) = (vm.fuel.as_mut(), self.event.opcode == FINISH) {
+            *fuel += 1;
+        }
+
+        vm.memory.relative_base = self.relative_base;
+        vm.ip = self.event.ip;
+    }
+}
+
+/// Executes a single instruction like `Vm::step`, and also returns the record
+/// for undoing it. There is no record when nothing was executed.
+pub fn step_recorded(vm: &mut Vm) -> Result<(Option<StepResult>, Option<Record>), VmError> {
+    let relative_base = vm.memory.relative_base;
+    let overwritten = destination(vm).map(|address| (address, vm.memory.read_memory(address)));
+
+    let (result, event) = vm.step_event()?;
+    let record = event.map(|event| Record { event, relative_base, overwritten: event.write.and(overwritten) });
+
+    Ok((result, record))
+}
+
+/// Address the instruction at the ip would write to.
+fn destination(vm: &Vm) -> Option<usize> {
+    let ip = vm.ip;
+    let op = vm.memory.fetch_op(ip).ok()?;
+    match op.opcode {
+        ADD | MULTIPLY | LESS_THAN | EQUALS => vm.memory.destination(ip, &op.params[2]).ok(),
+        INPUT => vm.memory.destination(ip, &op.params[0]).ok(),
+        _ => None,
+    }
+}
+
+/// Runs the machine while recording every executed instruction, so it can be
+/// rewound to any earlier point. Running forward again from there with the
+/// same input, which is queued again when rewinding, repeats what happened.
+pub struct Recorder {
+    pub vm: Vm,
+    history: Vec<Record>,
+}
+
+impl Recorder {
+    pub fn new(vm: Vm) -> Recorder {
+        Recorder { vm, history: Vec::new() }
+    }
+
+    /// Number of instructions executed so far.
+    pub fn executed(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn history(&self) -> &[Record] {
+        &self.history
+    }
+
+    /// Everything the program output until now.
+    pub fn output(&self) -> Vec<i64> {
+        self.history.iter().filter_map(|record| record.event.output).collect()
+    }
+
+    /// Whether the last executed instruction was the HALT the machine is at.
+    pub fn halted(&self) -> bool {
+        self.history.last().is_some_and(|record| record.event.opcode == FINISH && record.event.ip == self.vm.ip())
+    }
+
+    /// Executes a single instruction, except on a halted machine, which isn't
+    /// recorded halting again.
+    pub fn step(&mut self) -> Result<Option<StepResult>, VmError> {
+        if self.halted() {
+            return Ok(Some(StepResult::Halted));
+        }
+
+        let (result, record) = step_recorded(&mut self.vm)?;
+        if let Some(record) = record {
+            self.history.push(record);
+        }
+
+        Ok(result)
+    }
+
+    /// Runs until the program needs more input than queued, produces an
+    /// output, halts or runs out of fuel, like `Vm::run`.
+    pub fn run(&mut self) -> Result<StepResult, VmError> {
+        loop {
+            if let Some(result) = self.step()? {
+                return Ok(result);
+            }
+        }
+    }
+
+    pub fn run_until_input(&mut self) -> Result<(Vec<i64>, StepResult), VmError> {
+        let mut output = Vec::new();
+        loop {
+            match self.run()? {
+                StepResult::Output(value) => output.push(value),
+                state => return Ok((output, state)),
+            }
+        }
+    }
+
+    /// Undoes the last executed instruction. Returns false when there's none.
+    pub fn step_back(&mut self) -> bool {
+        match self.history.pop() {
+            Some(record) => {
+                record.undo(&mut self.vm);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Undoes instructions until only `executed` of them were executed.
+    pub fn rewind_to(&mut self, executed: usize) {
+        while self.history.len() > executed && self.step_back() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::assemble;
+
+    #[test]
+    fn test_rewind_and_replay() {
+        let program = assemble("
+                    ARB #1000
+            loop:   IN -> [rb+0]
+                    ADD [rb+0], [total] -> [total]
+                    OUT [total]
+                    ARB #1
+                    JT #1, #loop
+            total:  DATA 0
+        ").unwrap();
+
+        let mut recorder = Recorder::new(Vm::new(program.clone()));
+        recorder.vm.push_inputs(&[1, 2, 3]);
+        assert_eq!(recorder.run_until_input(), Ok((vec![1, 3, 6], StepResult::NeedInput)));
+        let executed = recorder.executed();
+        let snapshot = recorder.vm.to_snapshot();
+
+        // Back to before the second input was read
+        recorder.rewind_to(6);
+        assert_eq!(recorder.vm.ip(), 2);
+        assert_eq!(recorder.vm.memory.relative_base, 1001);
+        assert_eq!(recorder.vm.memory.read_memory(1001), 0);
+        assert_eq!(recorder.vm.pending_input(), &[2, 3]);
+        assert_eq!(recorder.output(), vec![1]);
+
+        assert_eq!(recorder.run_until_input(), Ok((vec![3, 6], StepResult::NeedInput)));
+        assert_eq!(recorder.executed(), executed);
+        assert_eq!(recorder.vm.to_snapshot(), snapshot);
+
+        recorder.rewind_to(0);
+        assert_eq!(recorder.vm.to_snapshot(), { let mut vm = Vm::new(program); vm.push_inputs(&[1, 2, 3]); vm.to_snapshot() });
+        assert!(!recorder.step_back());
+    }
+
+    #[test]
+    fn test_run_halted() {
+        let mut vm = Vm::new(vec![104, 5, 99]);
+        vm.set_fuel(Some(10));
+        let mut recorder = Recorder::new(vm);
+        assert_eq!(recorder.run_until_input(), Ok((vec![5], StepResult::Halted)));
+        assert!(recorder.halted());
+
+        // Running again doesn't record another HALT
+        assert_eq!(recorder.run(), Ok(StepResult::Halted));
+        assert_eq!(recorder.run_until_input(), Ok((vec![], StepResult::Halted)));
+        assert_eq!(recorder.executed(), 2);
+        assert_eq!(recorder.vm.fuel(), Some(9));
+
+        recorder.rewind_to(1);
+        assert!(!recorder.halted());
+        assert_eq!(recorder.vm.fuel(), Some(9));
+        recorder.rewind_to(0);
+        assert_eq!(recorder.vm.fuel(), Some(10));
+    }
+}
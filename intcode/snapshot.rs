@@ -0,0 +1,192 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{Cell, Memory, Vm};
+use crate::storage::Storage;
+
+/// Saving the complete state of the virtual machine as text, one field per line:
+///
+/// ```text
+/// ip 4
+/// relative_base 0
+/// input 1,2
+/// program 3,7,4,7,99,0,0,0
+/// virtual_memory 1000=5,1001=-3
+/// fuel 500
+/// ```
+///
+/// The last line is only there for machines with limited fuel.
+impl<C: Cell, S: Storage<C>> Vm<C, S> {
+    pub fn to_snapshot(&self) -> String {
+        let virtual_memory = self.memory.virtual_memory.values();
+
+        let mut snapshot = format!("ip {}\nrelative_base {}\ninput {}\nprogram {}\nvirtual_memory {}\n",
+            self.ip,
+            self.memory.relative_base,
+            join(self.input.iter()),
+            join(self.memory.program().iter()),
+            virtual_memory.iter().map(|(address, value)| format!("{}={}", address, value)).collect::<Vec<String>>().join(","));
+
+        if let Some(fuel) = self.fuel {
+            snapshot += &format!("fuel {}\n", fuel);
+        }
+
+        snapshot
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_snapshot())
+    }
+}
+
+/// Snapshots can be loaded into any storage, as they only list the values.
+impl<C: Cell + FromStr, S: Storage<C>> Vm<C, S> {
+    pub fn from_snapshot(snapshot: &str) -> io::Result<Vm<C, S>> {
+        let mut lines = snapshot.lines();
+
+        let ip: usize = parse(field(&mut lines, "ip")?)?;
+        let relative_base: C = parse(field(&mut lines, "relative_base")?)?;
+        let input: VecDeque<C> = parse_list(field(&mut lines, "input")?)?.into_iter().collect();
+        let program: Vec<C> = parse_list(field(&mut lines, "program")?)?;
+
+        let mut memory: Memory<C, S> = Memory::with_storage(program);
+        memory.relative_base = relative_base;
+        for pair in split_list(field(&mut lines, "virtual_memory")?) {
+            match pair.find('=') {
+                Some(separator) => {
+                    let address: usize = parse(&pair[..separator])?;
+                    if address < memory.program().len() {
+                        return Err(invalid(format!("Address {} in virtual memory is part of the program", address)));
+                    }
+                    memory.virtual_memory.write(address, parse(&pair[separator + 1..])?);
+                },
+                None => return Err(invalid(format!("Expected address=value, found {}", pair))),
+            }
+        }
+
+        let fuel = match lines.next() {
+            Some(line) => Some(parse(field(&mut Some(line).into_iter(), "fuel")?)?),
+            None => None,
+        };
+        if let Some(line) = lines.next() {
+            return Err(invalid(format!("Unexpected line {}", line)));
+        }
+
+        Ok(Vm { memory, ip, input, fuel })
+    }
+
+    pub fn load(path: &Path) -> io::Result<Vm<C, S>> {
+        Vm::from_snapshot(&fs::read_to_string(path)?)
+    }
+}
+
+fn join<'a, C: Cell + 'a, I: Iterator<Item = &'a C>>(values: I) -> String {
+    values.map(|value| value.to_string()).collect::<Vec<String>>().join(",")
+}
+
+fn field<'a, I: Iterator<Item = &'a str>>(lines: &mut I, name: &str) -> io::Result<&'a str> {
+    let line = lines.next().ok_or_else(|| invalid(format!("Missing field {}", name)))?;
+
+    if line == name {
+        Ok("")
+    }
+    else if line.starts_with(name) && line[name.len()..].starts_with(' ') {
+        Ok(&line[name.len() + 1..])
+    }
+    else {
+        Err(invalid(format!("Expected field {}, found {}", name, line)))
+    }
+}
+
+fn split_list(list: &str) -> Vec<&str> {
+    list.split(',').filter(|value| !value.is_empty()).collect()
+}
+
+fn parse_list<T: FromStr>(list: &str) -> io::Result<Vec<T>> {
+    split_list(list).into_iter().map(parse).collect()
+}
+
+fn parse<T: FromStr>(value: &str) -> io::Result<T> {
+    value.parse::<T>().map_err(|_| invalid(format!("Couldn't parse number {}", value)))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{to_cells, BigInt, StepResult};
+
+    #[test]
+    fn test_clone_resumes_independently() {
+        let mut vm = Vm::new(vec![3, 9, 4, 9, 3, 9, 4, 9, 99, 0]);
+        vm.push_input(1);
+        assert_eq!(vm.run(), Ok(StepResult::Output(1)));
+
+        let mut branch = vm.clone();
+        vm.push_input(2);
+        branch.push_input(3);
+
+        assert_eq!(vm.run_to_end(), Ok(vec![2]));
+        assert_eq!(branch.run_to_end(), Ok(vec![3]));
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut vm = Vm::new(vec![109, 5, 21101, 2, 3, 1000, 3, 100, 3, 100, 4, 1005, 99]);
+        vm.push_input(7);
+        assert_eq!(vm.run(), Ok(StepResult::NeedInput));
+
+        vm.push_inputs(&[-4, 6]);
+        let snapshot = vm.to_snapshot();
+        assert_eq!(snapshot, "ip 8\nrelative_base 5\ninput -4,6\nprogram 109,5,21101,2,3,1000,3,100,3,100,4,1005,99\nvirtual_memory 100=7,1005=5\n");
+
+        let mut restored: Vm = Vm::from_snapshot(&snapshot).unwrap();
+        assert_eq!(restored.to_snapshot(), snapshot);
+        assert_eq!(restored.run_to_end(), vm.run_to_end());
+
+        vm.set_fuel(Some(12));
+        assert!(vm.to_snapshot().ends_with("\nfuel 12\n"));
+        assert_eq!(load(&vm.to_snapshot()).unwrap().fuel(), Some(12));
+    }
+
+    #[test]
+    fn test_big_snapshot_round_trip() {
+        let mut vm: Vm<BigInt> = Vm::from_program(to_cells(&[1102, 1 << 32, 1 << 31, 1000, 3, 9, 4, 1000, 99, 0]));
+        assert_eq!(vm.run(), Ok(StepResult::NeedInput));
+
+        vm.push_input("18446744073709551616".parse().unwrap());
+        let snapshot = vm.to_snapshot();
+        assert!(snapshot.contains("\ninput 18446744073709551616\n"));
+        assert!(snapshot.contains("\nvirtual_memory 1000=9223372036854775808\n"));
+
+        let mut restored: Vm<BigInt, HashMap<usize, BigInt>> = Vm::from_snapshot(&snapshot).unwrap();
+        assert_eq!(restored.to_snapshot(), snapshot);
+        assert_eq!(restored.run_to_end(), Ok(vec!["9223372036854775808".parse().unwrap()]));
+        assert_eq!(restored.memory.read_memory(9), "18446744073709551616".parse().unwrap());
+    }
+
+    fn load(snapshot: &str) -> io::Result<Vm> {
+        Vm::from_snapshot(snapshot)
+    }
+
+    #[test]
+    fn test_invalid_snapshot() {
+        assert!(load("ip 0\nrelative_base x\n").is_err());
+        assert!(load("ip 0\nrelative_base 0\ninput\nprogram 99\n").is_err());
+        assert!(load("ip 0\nrelative_base 0\ninput\nprogram 99\nvirtual_memory 5\n").is_err());
+        assert!(load("ip 0\nrelative_base 0\ninput\nprogram 99\nvirtual_memory\n").is_ok());
+        assert!(load("ip 0\nrelative_base 0\ninput\nprogram 99\nvirtual_memory\nfuel\n").is_err());
+        assert!(load("ip 0\nrelative_base 0\ninput\nprogram 99,0\nvirtual_memory 1=5\n").is_err());
+        assert!(load("ip 0\nrelative_base 0\ninput\nprogram 99,0\nvirtual_memory 2=5\n").is_ok());
+        assert!(load("ip 0\nrelative_base 0\ninput\nprogram 99\nvirtual_memory\nfuel 5\nip 1\n").is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use crate::Cell;
+
+const PAGE_SIZE: usize = 1024;
+const MAX_PAGES: usize = 1 << 16;
+
+/// Memory past the end of the program, where every address starts out as 0.
+pub trait Storage<C: Cell = i64>: Default + Clone {
+    fn read(&self, address: usize) -> C;
+    fn write(&mut self, address: usize, value: C);
+
+    /// Addresses holding a value other than 0, in increasing order.
+    fn values(&self) -> Vec<(usize, C)>;
+}
+
+impl<C: Cell> Storage<C> for HashMap<usize, C> {
+    fn read(&self, address: usize) -> C {
+        self.get(&address).cloned().unwrap_or_default()
+    }
+
+    fn write(&mut self, address: usize, value: C) {
+        self.insert(address, value);
+    }
+
+    fn values(&self) -> Vec<(usize, C)> {
+        let mut values: Vec<(usize, C)> = self.iter().filter(|entry| !entry.1.is_zero()).map(|(address, value)| (*address, value.clone())).collect();
+        values.sort_by_key(|entry| entry.0);
+        values
+    }
+}
+
+/// Storage split into pages of consecutive addresses, which are allocated on
+/// the first write. Addresses past the first `MAX_PAGES` pages are kept in a
+/// map, so a single write far away doesn't allocate everything before it.
+#[derive(Clone)]
+pub struct PagedMemory<C: Cell = i64> {
+    pages: Vec<Option<Box<[C]>>>,
+    far: HashMap<usize, C>,
+}
+
+impl<C: Cell> Default for PagedMemory<C> {
+    fn default() -> PagedMemory<C> {
+        PagedMemory { pages: Vec::new(), far: HashMap::new() }
+    }
+}
+
+impl<C: Cell> Storage<C> for PagedMemory<C> {
+    fn read(&self, address: usize) -> C {
+        match self.pages.get(address / PAGE_SIZE) {
+            Some(Some(page)) => page[address % PAGE_SIZE].clone(),
+            Some(None) => C::default(),
+            None if address / PAGE_SIZE < MAX_PAGES => C::default(),
+            None => self.far.read(address),
+        }
+    }
+
+    fn write(&mut self, address: usize, value: C) {
+        let index = address / PAGE_SIZE;
+        if index >= MAX_PAGES {
+            self.far.write(address, value);
+            return;
+        }
+
+        if index >= self.pages.len() {
+            self.pages.resize(index + 1, None);
+        }
+
+        let page = self.pages[index].get_or_insert_with(|| vec![C::default(); PAGE_SIZE].into_boxed_slice());
+        page[address % PAGE_SIZE] = value;
+    }
+
+    fn values(&self) -> Vec<(usize, C)> {
+        let mut values = Vec::new();
+        for (index, page) in self.pages.iter().enumerate() {
+            if let Some(page) = page {
+                for (offset, value) in page.iter().enumerate() {
+                    if !value.is_zero() {
+                        values.push((index * PAGE_SIZE + offset, value.clone()));
+                    }
+                }
+            }
+        }
+
+        values.extend(Storage::values(&self.far));
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn check_storage<S: Storage>() {
+        let mut storage = S::default();
+        assert_eq!(storage.read(5000), 0);
+
+        storage.write(5000, 7);
+        storage.write(3, -1);
+        storage.write(1 << 40, 9);
+        storage.write(6000, 0);
+        assert_eq!(storage.read(5000), 7);
+        assert_eq!(storage.read(5001), 0);
+        assert_eq!(storage.read(1 << 40), 9);
+        assert_eq!(storage.values(), vec![(3, -1), (5000, 7), (1 << 40, 9)]);
+    }
+
+    #[test]
+    fn test_hash_map() {
+        check_storage::<HashMap<usize, i64>>();
+    }
+
+    #[test]
+    fn test_paged_memory() {
+        check_storage::<PagedMemory>();
+    }
+}
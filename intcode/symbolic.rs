@@ -0,0 +1,816 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::{parse_instruction, ParameterMode, VmError};
+use crate::memory::to_address;
+use crate::{ADD, MULTIPLY, INPUT, OUTPUT, JMP_TRUE, JMP_FALSE, LESS_THAN, EQUALS, ADJUST_BASE, FINISH};
+
+const MAX_STEPS: usize = 100_000;
+const MAX_PATHS: usize = 1_000;
+
+/// Number of tried values after which the solver gives up.
+const SOLVER_BUDGET: usize = 100_000;
+const PROPAGATION_ROUNDS: usize = 100;
+
+/// Value computed by a program from its input, where `Input(0)` is the first
+/// value it reads. Sums are kept as a sum of distinct terms with constant
+/// factors, followed by the constant, when the expression is built.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Expr {
+    Const(i64),
+    Input(usize),
+    Add(Rc<Expr>, Rc<Expr>),
+    Mul(Rc<Expr>, Rc<Expr>),
+    LessThan(Rc<Expr>, Rc<Expr>),
+    Equals(Rc<Expr>, Rc<Expr>),
+}
+
+impl Expr {
+    pub fn sum(a: Expr, b: Expr) -> Expr {
+        Sum::of(&a).plus(Sum::of(&b)).to_expr()
+    }
+
+    pub fn product(a: Expr, b: Expr) -> Expr {
+        match (a.constant(), b.constant()) {
+            (_, Some(factor)) => Sum::of(&a).scaled(factor).to_expr(),
+            (Some(factor), _) => Sum::of(&b).scaled(factor).to_expr(),
+            _ => Expr::Mul(Rc::new(a), Rc::new(b)),
+        }
+    }
+
+    pub fn less_than(a: Expr, b: Expr) -> Expr {
+        match (a, b) {
+            (Expr::Const(a), Expr::Const(b)) => Expr::Const((a < b) as i64),
+            (ref a, ref b) if a == b => Expr::Const(0),
+            (a, b) => Expr::LessThan(Rc::new(a), Rc::new(b)),
+        }
+    }
+
+    pub fn equals(a: Expr, b: Expr) -> Expr {
+        let difference = Sum::of(&a).plus(Sum::of(&b).scaled(-1));
+        if difference.terms.is_empty() {
+            Expr::Const((difference.constant == 0) as i64)
+        }
+        else {
+            Expr::Equals(Rc::new(a), Rc::new(b))
+        }
+    }
+
+    /// Value of the expression when it doesn't depend on the input.
+    pub fn constant(&self) -> Option<i64> {
+        match *self {
+            Expr::Const(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Value of the expression for the input, with arithmetic wrapping
+    /// around like in the virtual machine.
+    pub fn evaluate(&self, input: &[i64]) -> i64 {
+        match self {
+            Expr::Const(value) => *value,
+            Expr::Input(index) => input[*index],
+            Expr::Add(a, b) => a.evaluate(input).wrapping_add(b.evaluate(input)),
+            Expr::Mul(a, b) => a.evaluate(input).wrapping_mul(b.evaluate(input)),
+            Expr::LessThan(a, b) => (a.evaluate(input) < b.evaluate(input)) as i64,
+            Expr::Equals(a, b) => (a.evaluate(input) == b.evaluate(input)) as i64,
+        }
+    }
+
+    fn collect_inputs(&self, inputs: &mut BTreeSet<usize>) {
+        match self {
+            Expr::Const(_) => {},
+            Expr::Input(index) => {
+                inputs.insert(*index);
+            },
+            Expr::Add(a, b) | Expr::Mul(a, b) | Expr::LessThan(a, b) | Expr::Equals(a, b) => {
+                a.collect_inputs(inputs);
+                b.collect_inputs(inputs);
+            },
+        }
+    }
+
+    fn is_comparison(&self) -> bool {
+        matches!(self, Expr::LessThan(_, _) | Expr::Equals(_, _))
+    }
+
+    /// The expression as a sum of inputs with constant factors, if it is one.
+    fn linear(&self) -> Option<Linear> {
+        match self {
+            Expr::Const(value) => Some(Linear { constant: *value as i128, terms: BTreeMap::new() }),
+            Expr::Input(index) => Some(Linear { constant: 0, terms: vec![(*index, 1)].into_iter().collect() }),
+            Expr::Add(a, b) => a.linear()?.plus(&b.linear()?),
+            Expr::Mul(a, b) => {
+                let (a, b) = (a.linear()?, b.linear()?);
+                if a.terms.is_empty() {
+                    b.scaled(a.constant)
+                }
+                else if b.terms.is_empty() {
+                    a.scaled(b.constant)
+                }
+                else {
+                    None
+                }
+            },
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Const(value) => write!(f, "{}", value),
+            Expr::Input(index) => write!(f, "in{}", index),
+            Expr::Add(a, b) => match &**b {
+                Expr::Const(value) if *value < 0 => write!(f, "({} - {})", a, value.unsigned_abs()),
+                Expr::Mul(term, factor) if **factor == Expr::Const(-1) => write!(f, "({} - {})", a, term),
+                _ => write!(f, "({} + {})", a, b),
+            },
+            Expr::Mul(a, b) => write!(f, "({} * {})", a, b),
+            Expr::LessThan(a, b) => write!(f, "({} < {})", a, b),
+            Expr::Equals(a, b) => write!(f, "({} == {})", a, b),
+        }
+    }
+}
+
+/// Terms of an expression with their factors, which wrap around like the
+/// values in the virtual machine, so rearranging them doesn't change the
+/// value of the expression.
+struct Sum {
+    constant: i64,
+    terms: Vec<(Expr, i64)>,
+}
+
+impl Sum {
+    fn of(expr: &Expr) -> Sum {
+        match expr {
+            Expr::Const(value) => Sum { constant: *value, terms: Vec::new() },
+            Expr::Add(a, b) => Sum::of(a).plus(Sum::of(b)),
+            Expr::Mul(a, b) => match (a.constant(), b.constant()) {
+                (_, Some(factor)) => Sum::of(a).scaled(factor),
+                (Some(factor), _) => Sum::of(b).scaled(factor),
+                _ => Sum { constant: 0, terms: vec![(expr.clone(), 1)] },
+            },
+            _ => Sum { constant: 0, terms: vec![(expr.clone(), 1)] },
+        }
+    }
+
+    fn plus(mut self, other: Sum) -> Sum {
+        self.constant = self.constant.wrapping_add(other.constant);
+        for (term, factor) in other.terms {
+            match self.terms.iter().position(|(existing, _)| *existing == term) {
+                Some(index) => self.terms[index].1 = self.terms[index].1.wrapping_add(factor),
+                None => self.terms.push((term, factor)),
+            }
+        }
+        self.terms.retain(|(_, factor)| *factor != 0);
+        self
+    }
+
+    fn scaled(mut self, factor: i64) -> Sum {
+        self.constant = self.constant.wrapping_mul(factor);
+        for term in self.terms.iter_mut() {
+            term.1 = term.1.wrapping_mul(factor);
+        }
+        self.terms.retain(|(_, factor)| *factor != 0);
+        self
+    }
+
+    fn to_expr(&self) -> Expr {
+        let mut sum: Option<Expr> = None;
+        for (term, factor) in &self.terms {
+            let term = match factor {
+                1 => term.clone(),
+                _ => Expr::Mul(Rc::new(term.clone()), Rc::new(Expr::Const(*factor))),
+            };
+            sum = Some(match sum {
+                Some(sum) => Expr::Add(Rc::new(sum), Rc::new(term)),
+                None => term,
+            });
+        }
+
+        match sum {
+            None => Expr::Const(self.constant),
+            Some(sum) if self.constant == 0 => sum,
+            Some(sum) => Expr::Add(Rc::new(sum), Rc::new(Expr::Const(self.constant))),
+        }
+    }
+}
+
+/// `constant + sum of factor * input`, computed without wrapping around.
+#[derive(Clone)]
+struct Linear {
+    constant: i128,
+    terms: BTreeMap<usize, i128>,
+}
+
+/// Factors and constants are kept within the range of the machine's values.
+fn in_range(value: i128) -> Option<i128> {
+    if value >= i64::MIN as i128 && value <= i64::MAX as i128 {
+        Some(value)
+    }
+    else {
+        None
+    }
+}
+
+impl Linear {
+    fn plus(&self, other: &Linear) -> Option<Linear> {
+        let mut sum = self.clone();
+        sum.constant = in_range(sum.constant + other.constant)?;
+        for (index, factor) in &other.terms {
+            let total = in_range(sum.terms.get(index).unwrap_or(&0) + factor)?;
+            if total == 0 {
+                sum.terms.remove(index);
+            }
+            else {
+                sum.terms.insert(*index, total);
+            }
+        }
+        Some(sum)
+    }
+
+    fn scaled(&self, factor: i128) -> Option<Linear> {
+        let mut terms = BTreeMap::new();
+        if factor != 0 {
+            for (index, value) in &self.terms {
+                terms.insert(*index, in_range(value * factor)?);
+            }
+        }
+        Some(Linear { constant: in_range(self.constant * factor)?, terms })
+    }
+
+    fn minus(&self, other: &Linear) -> Option<Linear> {
+        self.plus(&other.scaled(-1)?)
+    }
+
+    fn offset(&self, value: i128) -> Linear {
+        Linear { constant: self.constant + value, terms: self.terms.clone() }
+    }
+}
+
+/// Condition of a jump on a path, which is either non-zero or zero there.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Constraint {
+    pub condition: Expr,
+    pub nonzero: bool,
+}
+
+impl Constraint {
+    pub fn holds(&self, input: &[i64]) -> bool {
+        (self.condition.evaluate(input) != 0) == self.nonzero
+    }
+
+    /// The same constraint without the comparisons with zero that programs
+    /// use to negate conditions.
+    fn normalized(&self) -> Constraint {
+        let mut constraint = self.clone();
+        loop {
+            let inner = match &constraint.condition {
+                Expr::Equals(a, b) if a.is_comparison() && **b == Expr::Const(0) => (**a).clone(),
+                Expr::Equals(a, b) if b.is_comparison() && **a == Expr::Const(0) => (**b).clone(),
+                _ => return constraint,
+            };
+            constraint = Constraint { condition: inner, nonzero: !constraint.nonzero };
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let constraint = self.normalized();
+        match (&constraint.condition, constraint.nonzero) {
+            (Expr::LessThan(a, b), true) => write!(f, "{} < {}", a, b),
+            (Expr::LessThan(a, b), false) => write!(f, "{} >= {}", a, b),
+            (Expr::Equals(a, b), true) => write!(f, "{} == {}", a, b),
+            (Expr::Equals(a, b), false) => write!(f, "{} != {}", a, b),
+            (condition, true) => write!(f, "{} != 0", condition),
+            (condition, false) => write!(f, "{} == 0", condition),
+        }
+    }
+}
+
+/// Constraint in the form used by the solver.
+enum Atom {
+    /// The sum is at most zero.
+    AtMost(Linear),
+    NotZero(Linear),
+    /// Non-linear constraint, checked once all its inputs have a value.
+    Other(Constraint, Vec<usize>),
+}
+
+fn atoms(constraint: &Constraint) -> Vec<Atom> {
+    let constraint = constraint.normalized();
+    let equal = |sum: Linear| vec![Atom::AtMost(sum.scaled(-1).unwrap()), Atom::AtMost(sum)];
+
+    let linear = match (&constraint.condition, constraint.nonzero) {
+        (Expr::LessThan(a, b), true) => a.linear().and_then(|a| a.minus(&b.linear()?)).map(|sum| vec![Atom::AtMost(sum.offset(1))]),
+        (Expr::LessThan(a, b), false) => b.linear().and_then(|b| b.minus(&a.linear()?)).map(|sum| vec![Atom::AtMost(sum)]),
+        (Expr::Equals(a, b), true) => a.linear().and_then(|a| a.minus(&b.linear()?)).map(equal),
+        (Expr::Equals(a, b), false) => a.linear().and_then(|a| a.minus(&b.linear()?)).map(|sum| vec![Atom::NotZero(sum)]),
+        (condition, true) => condition.linear().map(|sum| vec![Atom::NotZero(sum)]),
+        (condition, false) => condition.linear().map(equal),
+    };
+
+    linear.unwrap_or_else(|| {
+        let mut inputs = BTreeSet::new();
+        constraint.condition.collect_inputs(&mut inputs);
+        vec![Atom::Other(constraint, inputs.into_iter().collect())]
+    })
+}
+
+/// Result of solving constraints on the input.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Solution {
+    /// Input satisfying all the constraints.
+    Satisfiable(Vec<i64>),
+    Unsatisfiable,
+    /// The solver gave up before finding out.
+    Unknown,
+}
+
+/// Looks for `inputs` values within `range` satisfying all the constraints.
+/// Bounds implied by linear constraints are propagated, assuming nothing
+/// wraps around, and the remaining values are searched one by one until the
+/// solver's budget runs out. Solutions are checked with wrapping arithmetic.
+pub fn solve(constraints: &[Constraint], inputs: usize, range: Range<i64>) -> Solution {
+    if range.is_empty() {
+        return if inputs == 0 { check(constraints, Vec::new()) } else { Solution::Unsatisfiable };
+    }
+
+    let atoms: Vec<Atom> = constraints.iter().flat_map(atoms).collect();
+    let mut constrained = BTreeSet::new();
+    for constraint in constraints {
+        constraint.condition.collect_inputs(&mut constrained);
+    }
+
+    let default = default_input(&range).unwrap() as i128;
+    let domains: Vec<(i128, i128)> = (0..inputs).map(|index| match constrained.contains(&index) {
+        true => (range.start as i128, range.end as i128 - 1),
+        false => (default, default),
+    }).collect();
+
+    let mut budget = SOLVER_BUDGET;
+    match search(&atoms, constraints, domains, &mut budget) {
+        Some(input) => Solution::Satisfiable(input),
+        None if budget == 0 => Solution::Unknown,
+        None => Solution::Unsatisfiable,
+    }
+}
+
+/// Value of inputs without constraints, which is as close to zero as the
+/// range allows, unless it's empty.
+fn default_input(range: &Range<i64>) -> Option<i64> {
+    if range.is_empty() {
+        None
+    }
+    else {
+        Some((0i64).clamp(range.start, range.end - 1))
+    }
+}
+
+fn check(constraints: &[Constraint], input: Vec<i64>) -> Solution {
+    if constraints.iter().all(|constraint| constraint.holds(&input)) {
+        Solution::Satisfiable(input)
+    }
+    else {
+        Solution::Unsatisfiable
+    }
+}
+
+fn search(atoms: &[Atom], constraints: &[Constraint], mut domains: Vec<(i128, i128)>, budget: &mut usize) -> Option<Vec<i64>> {
+    if *budget == 0 {
+        return None;
+    }
+    *budget -= 1;
+
+    if !propagate(atoms, &mut domains) {
+        return None;
+    }
+
+    let open = (0..domains.len()).filter(|index| domains[*index].0 < domains[*index].1).min_by_key(|index| domains[*index].1 - domains[*index].0);
+    match open {
+        None => match check(constraints, domains.iter().map(|(value, _)| *value as i64).collect()) {
+            Solution::Satisfiable(input) => Some(input),
+            _ => None,
+        },
+        Some(index) => {
+            let (low, high) = domains[index];
+            for value in low..=high {
+                let mut narrowed = domains.clone();
+                narrowed[index] = (value, value);
+                if let Some(input) = search(atoms, constraints, narrowed, budget) {
+                    return Some(input);
+                }
+                if *budget == 0 {
+                    return None;
+                }
+            }
+            None
+        },
+    }
+}
+
+/// Narrows the domains of the inputs to the values which can still satisfy
+/// the constraints. Returns false when there are none.
+fn propagate(atoms: &[Atom], domains: &mut [(i128, i128)]) -> bool {
+    let fixed = |domains: &[(i128, i128)], index: usize| domains[index].0 == domains[index].1;
+
+    for _ in 0..PROPAGATION_ROUNDS {
+        let mut changed = false;
+        for atom in atoms {
+            match atom {
+                Atom::AtMost(sum) => {
+                    let smallest = |domains: &[(i128, i128)], index: usize, factor: i128| (factor * domains[index].0).min(factor * domains[index].1);
+                    let minimum = sum.terms.iter().fold(sum.constant, |total, (index, factor)| total.saturating_add(smallest(domains, *index, *factor)));
+                    if minimum > 0 {
+                        return false;
+                    }
+
+                    // factor * input <= bound, with every other input at its smallest
+                    for (index, factor) in &sum.terms {
+                        let bound = -(minimum - smallest(domains, *index, *factor));
+                        let (low, high) = domains[*index];
+                        let narrowed = if *factor > 0 {
+                            (low, high.min(bound.div_euclid(*factor)))
+                        }
+                        else {
+                            (low.max(-bound.div_euclid(-factor)), high)
+                        };
+
+                        if narrowed.0 > narrowed.1 {
+                            return false;
+                        }
+                        if narrowed != (low, high) {
+                            domains[*index] = narrowed;
+                            changed = true;
+                        }
+                    }
+                },
+                Atom::NotZero(sum) => {
+                    // Sums which don't fit aren't decided here
+                    if sum.terms.keys().all(|index| fixed(domains, *index)) {
+                        let value = sum.terms.iter().try_fold(sum.constant, |total, (index, factor)| {
+                            factor.checked_mul(domains[*index].0).and_then(|term| total.checked_add(term))
+                        });
+                        if value == Some(0) {
+                            return false;
+                        }
+                    }
+                },
+                Atom::Other(constraint, inputs) => {
+                    if inputs.iter().all(|index| fixed(domains, *index)) {
+                        let input: Vec<i64> = domains.iter().map(|(value, _)| *value as i64).collect();
+                        if !constraint.holds(&input) {
+                            return false;
+                        }
+                    }
+                },
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    true
+}
+
+/// How a path through the program ends.
+#[derive(PartialEq, Debug, Clone)]
+pub enum PathEnd {
+    Halted,
+    /// The program reads more values than the symbolic input has.
+    NeedInput,
+    /// An instruction, address or jump target depends on the input.
+    SymbolicAddress { ip: usize },
+    Error(VmError),
+    /// The path executed more instructions than allowed.
+    StepLimit,
+}
+
+/// One way through the program, with the constraints the input has to
+/// satisfy to take it and the output produced along the way.
+#[derive(PartialEq, Debug, Clone)]
+pub struct SymbolicPath {
+    pub constraints: Vec<Constraint>,
+    pub output: Vec<Expr>,
+    pub end: PathEnd,
+    /// Input taking the path, unless the solver gave up.
+    pub example: Option<Vec<i64>>,
+}
+
+/// Paths found by `SymbolicExecutor::explore`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Exploration {
+    pub paths: Vec<SymbolicPath>,
+    /// Number of paths left unexplored after reaching the limit, so the
+    /// paths are all of them only when it's 0.
+    pub unexplored: usize,
+}
+
+#[derive(Clone)]
+struct State {
+    written: BTreeMap<usize, Expr>,
+    ip: usize,
+    relative_base: i64,
+    inputs: usize,
+    constraints: Vec<Constraint>,
+    output: Vec<Expr>,
+    example: Option<Vec<i64>>,
+    steps: usize,
+}
+
+/// Jump on a condition depending on the input.
+struct Fork {
+    condition: Expr,
+    nonzero: usize,
+    zero: usize,
+}
+
+/// Runs a program on symbolic input, which is read as `in0`, `in1` and so on,
+/// and follows both ways of every jump depending on it. Only the ways some
+/// input within `range` can take are followed.
+pub struct SymbolicExecutor {
+    program: Vec<i64>,
+    inputs: usize,
+    range: Range<i64>,
+    max_steps: usize,
+    max_paths: usize,
+}
+
+impl SymbolicExecutor {
+    pub fn new(program: Vec<i64>, inputs: usize, range: Range<i64>) -> SymbolicExecutor {
+        SymbolicExecutor { program, inputs, range, max_steps: MAX_STEPS, max_paths: MAX_PATHS }
+    }
+
+    /// Limits the number of instructions executed on a single path, and the
+    /// number of paths explored.
+    pub fn with_limits(mut self, max_steps: usize, max_paths: usize) -> SymbolicExecutor {
+        self.max_steps = max_steps;
+        self.max_paths = max_paths;
+        self
+    }
+
+    pub fn explore(&self) -> Exploration {
+        let start = State {
+            written: BTreeMap::new(),
+            ip: 0,
+            relative_base: 0,
+            inputs: 0,
+            constraints: Vec::new(),
+            output: Vec::new(),
+            example: Some(Vec::new()),
+            steps: 0,
+        };
+
+        let mut paths = Vec::new();
+        let mut pending = vec![start];
+        while paths.len() < self.max_paths {
+            let mut state = match pending.pop() {
+                Some(state) => state,
+                None => break,
+            };
+
+            let end = loop {
+                match self.step(&mut state) {
+                    Ok(None) => {},
+                    Ok(Some(fork)) => match self.branches(&state, fork) {
+                        Some((first, others)) => {
+                            pending.extend(others);
+                            state = first;
+                        },
+                        None => break None,
+                    },
+                    Err(end) => break Some(end),
+                }
+            };
+
+            if let Some(end) = end {
+                paths.push(SymbolicPath { constraints: state.constraints, output: state.output, end, example: state.example });
+            }
+        }
+
+        Exploration { paths, unexplored: pending.len() }
+    }
+
+    /// Feasible ways of the jump, starting with the one taken by the input
+    /// found so far.
+    fn branches(&self, state: &State, fork: Fork) -> Option<(State, Vec<State>)> {
+        let mut branches = Vec::new();
+        for (nonzero, ip) in [(true, fork.nonzero), (false, fork.zero)] {
+            let mut branch = state.clone();
+            branch.constraints.push(Constraint { condition: fork.condition.clone(), nonzero });
+            branch.ip = ip;
+
+            let follows_example = state.example.as_ref().is_some_and(|example| branch.constraints.last().unwrap().holds(example));
+            if !follows_example {
+                branch.example = match solve(&branch.constraints, branch.inputs, self.range.clone()) {
+                    Solution::Satisfiable(input) => Some(input),
+                    Solution::Unsatisfiable => continue,
+                    Solution::Unknown => None,
+                };
+            }
+
+            if follows_example {
+                branches.insert(0, branch);
+            }
+            else {
+                branches.push(branch);
+            }
+        }
+
+        let mut branches = branches.into_iter();
+        branches.next().map(|first| (first, branches.collect()))
+    }
+
+    fn read(&self, state: &State, address: usize) -> Expr {
+        match state.written.get(&address) {
+            Some(value) => value.clone(),
+            None => Expr::Const(self.program.get(address).cloned().unwrap_or(0)),
+        }
+    }
+
+    fn address(&self, state: &State, ip: usize, offset: usize, mode: ParameterMode) -> Result<usize, PathEnd> {
+        let position = self.read(state, ip + offset).constant().ok_or(PathEnd::SymbolicAddress { ip })?;
+        let location = match mode {
+            ParameterMode::PositionMode => position,
+            ParameterMode::RelativeMode => state.relative_base.wrapping_add(position),
+            ParameterMode::ImmediateMode => return Err(PathEnd::Error(VmError::ImmediateModeWrite { ip })),
+            ParameterMode::InvalidMode(mode) => return Err(PathEnd::Error(VmError::InvalidParameterMode { ip, mode })),
+        };
+        to_address(ip, &location).map_err(PathEnd::Error)
+    }
+
+    fn parameter(&self, state: &State, ip: usize, offset: usize, mode: ParameterMode) -> Result<Expr, PathEnd> {
+        match mode {
+            ParameterMode::ImmediateMode => Ok(self.read(state, ip + offset)),
+            _ => Ok(self.read(state, self.address(state, ip, offset, mode)?)),
+        }
+    }
+
+    fn step(&self, state: &mut State) -> Result<Option<Fork>, PathEnd> {
+        if state.steps == self.max_steps {
+            return Err(PathEnd::StepLimit);
+        }
+        state.steps += 1;
+
+        let ip = state.ip;
+        let code = self.read(state, ip).constant().ok_or(PathEnd::SymbolicAddress { ip })?;
+        let instruction = parse_instruction(code);
+
+        match instruction.opcode {
+            ADD | MULTIPLY | LESS_THAN | EQUALS => {
+                let a = self.parameter(state, ip, 1, instruction.par1mode)?;
+                let b = self.parameter(state, ip, 2, instruction.par2mode)?;
+                let destination = self.address(state, ip, 3, instruction.par3mode)?;
+                let value = match instruction.opcode {
+                    ADD => Expr::sum(a, b),
+                    MULTIPLY => Expr::product(a, b),
+                    LESS_THAN => Expr::less_than(a, b),
+                    _ => Expr::equals(a, b),
+                };
+                state.written.insert(destination, value);
+                state.ip += 4;
+            },
+            INPUT => {
+                if state.inputs == self.inputs {
+                    return Err(PathEnd::NeedInput);
+                }
+                let destination = self.address(state, ip, 1, instruction.par1mode)?;
+                state.written.insert(destination, Expr::Input(state.inputs));
+                state.inputs += 1;
+                state.example = match (state.example.take(), default_input(&self.range)) {
+                    (Some(mut example), Some(value)) => {
+                        example.push(value);
+                        Some(example)
+                    },
+                    _ => None,
+                };
+                state.ip += 2;
+            },
+            OUTPUT => {
+                let value = self.parameter(state, ip, 1, instruction.par1mode)?;
+                state.output.push(value);
+                state.ip += 2;
+            },
+            JMP_TRUE | JMP_FALSE => {
+                let condition = self.parameter(state, ip, 1, instruction.par1mode)?;
+                let target = self.parameter(state, ip, 2, instruction.par2mode)?.constant().ok_or(PathEnd::SymbolicAddress { ip })?;
+                let target = to_address(ip, &target).map_err(PathEnd::Error)?;
+                let (nonzero, zero) = match instruction.opcode {
+                    JMP_TRUE => (target, ip + 3),
+                    _ => (ip + 3, target),
+                };
+
+                match condition.constant() {
+                    Some(0) => state.ip = zero,
+                    Some(_) => state.ip = nonzero,
+                    None => return Ok(Some(Fork { condition, nonzero, zero })),
+                }
+            },
+            ADJUST_BASE => {
+                let value = self.parameter(state, ip, 1, instruction.par1mode)?.constant().ok_or(PathEnd::SymbolicAddress { ip })?;
+                state.relative_base = state.relative_base.wrapping_add(value);
+                state.ip += 2;
+            },
+            FINISH => return Err(PathEnd::Halted),
+            opcode => return Err(PathEnd::Error(VmError::UnknownOpcode { ip, opcode })),
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{assemble, Vm};
+
+    fn less_than(a: Expr, b: Expr) -> Constraint {
+        Constraint { condition: Expr::less_than(a, b), nonzero: true }
+    }
+
+    #[test]
+    fn test_solve() {
+        let (x, y) = (Expr::Input(0), Expr::Input(1));
+        let sum = Constraint { condition: Expr::equals(Expr::sum(x.clone(), y.clone()), Expr::Const(10)), nonzero: true };
+        let constraints = vec![sum, less_than(x.clone(), y.clone()), less_than(Expr::Const(2), x.clone())];
+        assert_eq!(solve(&constraints, 2, 0..100), Solution::Satisfiable(vec![3, 7]));
+        assert_eq!(solve(&constraints, 2, 0..6), Solution::Unsatisfiable);
+
+        // Non-linear constraints are searched
+        let product = Constraint { condition: Expr::equals(Expr::product(x.clone(), y.clone()), Expr::Const(12)), nonzero: true };
+        let constraints = vec![product, less_than(y.clone(), x.clone()), less_than(Expr::Const(0), y)];
+        assert_eq!(solve(&constraints, 2, 0..50), Solution::Satisfiable(vec![4, 3]));
+
+        let never = vec![Constraint { condition: Expr::equals(Expr::product(x.clone(), x), Expr::Const(2)), nonzero: true }];
+        assert_eq!(solve(&never, 1, 0..1_000_000), Solution::Unknown);
+        assert_eq!(solve(&never, 1, 0..10), Solution::Unsatisfiable);
+
+        // Sums too big to compute exactly are left to the wrapping check
+        let term = |index| Expr::product(Expr::Input(index), Expr::Const(i64::MAX));
+        let big = Constraint { condition: Expr::sum(Expr::sum(term(0), term(1)), term(2)), nonzero: true };
+        assert_eq!(solve(&[big], 3, i64::MIN..i64::MIN + 1), Solution::Satisfiable(vec![i64::MIN; 3]));
+    }
+
+    #[test]
+    fn test_explore() {
+        let program = assemble("
+                    IN -> [x]
+                    LT [x], #10 -> [small]
+                    EQ [small], #0 -> [big]
+                    JT [big], #double
+                    OUT #0
+                    HALT
+            double: MUL [x], #2 -> [x]
+                    OUT [x]
+                    IN -> [y]
+                    JT [y], #jump
+                    HALT
+            jump:   JT #1, [y]
+            x:      DATA 0
+            small:  DATA 0
+            big:    DATA 0
+            y:      DATA 0
+        ").unwrap();
+
+        let exploration = SymbolicExecutor::new(program.clone(), 2, 0..100).explore();
+        assert_eq!(exploration.unexplored, 0);
+        let paths = exploration.paths;
+        let described: Vec<(String, String, PathEnd)> = paths.iter().map(|path| {
+            let constraints: Vec<String> = path.constraints.iter().map(|constraint| constraint.to_string()).collect();
+            let output: Vec<String> = path.output.iter().map(|value| value.to_string()).collect();
+            (constraints.join(", "), output.join(", "), path.end.clone())
+        }).collect();
+        assert_eq!(described, vec![
+            ("in0 < 10".to_string(), "0".to_string(), PathEnd::Halted),
+            ("in0 >= 10, in1 == 0".to_string(), "(in0 * 2)".to_string(), PathEnd::Halted),
+            ("in0 >= 10, in1 != 0".to_string(), "(in0 * 2)".to_string(), PathEnd::SymbolicAddress { ip: 28 }),
+        ]);
+
+        // The example inputs take their paths in the virtual machine
+        for path in &paths[..2] {
+            let example = path.example.clone().unwrap();
+            let mut vm = Vm::new(program.clone());
+            vm.push_inputs(&example);
+            let output: Vec<i64> = path.output.iter().map(|value| value.evaluate(&example)).collect();
+            assert_eq!(vm.run_until_input().unwrap().0, output);
+        }
+
+        let exploration = SymbolicExecutor::new(program.clone(), 1, 0..100).with_limits(100, 1).explore();
+        assert_eq!(exploration.paths.len(), 1);
+        assert_eq!(exploration.paths[0].example, Some(vec![0]));
+        assert_eq!(exploration.unexplored, 1);
+
+        // Ranges at the end of the values don't overflow
+        assert_eq!(SymbolicExecutor::new(program.clone(), 1, i64::MAX..i64::MAX).explore().paths, vec![]);
+        let paths = SymbolicExecutor::new(program, 1, i64::MAX - 1..i64::MAX).explore().paths;
+        assert_eq!(paths[0].example, Some(vec![i64::MAX - 1]));
+    }
+}
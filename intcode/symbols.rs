@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::take_flag_value;
+
+/// Names for addresses, or ranges of addresses like an inventory, kept as
+/// text with one name per line:
+///
+/// ```text
+/// 386 score
+/// 1500..1510 inventory
+/// ```
+///
+/// Addresses within a range are named after its start, like `inventory+3`.
+/// Lines starting with `;` are comments.
+#[derive(PartialEq, Debug, Default, Clone)]
+pub struct Symbols {
+    ranges: BTreeMap<usize, (usize, String)>,
+}
+
+impl Symbols {
+    pub fn new() -> Symbols {
+        Symbols::default()
+    }
+
+    /// Names the addresses in the range, replacing any range with the same start.
+    pub fn add(&mut self, range: Range<usize>, name: &str) {
+        self.ranges.insert(range.start, (range.end, name.to_string()));
+    }
+
+    /// Name of the range containing the address, with the offset from its start.
+    pub fn lookup(&self, address: usize) -> Option<(&str, usize)> {
+        let (start, (end, name)) = self.ranges.range(..=address).next_back()?;
+        if address < *end {
+            Some((name, address - start))
+        }
+        else {
+            None
+        }
+    }
+
+    /// The address written with its name, like `score` or `inventory+3`.
+    pub fn name(&self, address: usize) -> Option<String> {
+        self.lookup(address).map(|(name, offset)| match offset {
+            0 => name.to_string(),
+            _ => format!("{}+{}", name, offset),
+        })
+    }
+
+    /// Ranges ordered by their start.
+    pub fn ranges(&self) -> Vec<(Range<usize>, &str)> {
+        self.ranges.iter().map(|(start, (end, name))| (*start..*end, name.as_str())).collect()
+    }
+
+    pub fn parse(text: &str) -> io::Result<Symbols> {
+        let mut symbols = Symbols::new();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with(';')) {
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Expected address and name, found {}", line));
+
+            let (addresses, name) = match line.find(char::is_whitespace) {
+                Some(space) => (&line[..space], line[space..].trim()),
+                None => return Err(invalid()),
+            };
+            let range = match addresses.find("..") {
+                Some(dots) => addresses[..dots].parse().map_err(|_| invalid())?..addresses[dots + 2..].parse().map_err(|_| invalid())?,
+                None => {
+                    let address: usize = addresses.parse().map_err(|_| invalid())?;
+                    address..address + 1
+                },
+            };
+
+            if range.is_empty() || name.contains(char::is_whitespace) {
+                return Err(invalid());
+            }
+            symbols.add(range, name);
+        }
+
+        Ok(symbols)
+    }
+
+    /// Takes `--symbols <file>` out of command line arguments and loads the
+    /// file, or returns no symbols when it isn't given. Panics when the file
+    /// is missing or can't be read, like the binaries do with their other
+    /// arguments.
+    pub fn from_args(arguments: &mut Vec<String>) -> Symbols {
+        match take_flag_value(arguments, "--symbols") {
+            Some(path) => match Symbols::load(Path::new(&path)) {
+                Ok(symbols) => symbols,
+                Err(error) => panic!("Can't read symbols from {}: {}", path, error),
+            },
+            None => Symbols::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Symbols> {
+        Symbols::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for Symbols {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (range, name) in self.ranges() {
+            if range.len() == 1 {
+                writeln!(f, "{} {}", range.start, name)?;
+            }
+            else {
+                writeln!(f, "{}..{} {}", range.start, range.end, name)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        let symbols = Symbols::parse("; arcade\n386 score\n1500..1510 inventory\n").unwrap();
+        assert_eq!(symbols.name(386), Some("score".to_string()));
+        assert_eq!(symbols.name(387), None);
+        assert_eq!(symbols.name(1503), Some("inventory+3".to_string()));
+        assert_eq!(symbols.name(1510), None);
+        assert_eq!(symbols.to_string(), "386 score\n1500..1510 inventory\n");
+        assert_eq!(Symbols::parse(&symbols.to_string()).unwrap(), symbols);
+    }
+
+    #[test]
+    fn test_from_args() {
+        let mut arguments = vec!["program.txt".to_string(), "42".to_string()];
+        assert_eq!(Symbols::from_args(&mut arguments), Symbols::new());
+        assert_eq!(arguments, vec!["program.txt".to_string(), "42".to_string()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Missing file after --symbols")]
+    fn test_from_args_without_file() {
+        Symbols::from_args(&mut vec!["program.txt".to_string(), "--symbols".to_string()]);
+    }
+
+    #[test]
+    fn test_invalid_symbols() {
+        assert!(Symbols::parse("386\n").is_err());
+        assert!(Symbols::parse("x score\n").is_err());
+        assert!(Symbols::parse("10..5 backwards\n").is_err());
+        assert!(Symbols::parse("5 two names\n").is_err());
+    }
+}
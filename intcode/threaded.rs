@@ -0,0 +1,127 @@
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+use crate::{Cell, StepResult, Storage, Vm, VmError};
+
+/// Runs the machine on its own thread until it halts. Input is read from the
+/// receiver, waiting when nothing was sent yet, and every output value is sent
+/// to the sender. Output sent after the other end hung up is dropped.
+///
+/// The thread returns the halted machine, so its memory can be inspected.
+/// Input running out for good, because every sender hung up, is reported as
+/// `VmError::MissingInput`.
+pub fn spawn_vm<C, S>(vm: Vm<C, S>, input: Receiver<C>, output: Sender<C>) -> JoinHandle<Result<Vm<C, S>, VmError>>
+    where C: Cell + Send + Sync + 'static, S: Storage<C> + Send + 'static {
+    thread::spawn(move || run_connected(vm, input, output, None))
+}
+
+/// Same as `spawn_vm`, but instead of waiting for input the machine reads
+/// `no_data` when nothing was sent, like the network interfaces of day 23.
+pub fn spawn_polling_vm<C, S>(vm: Vm<C, S>, input: Receiver<C>, output: Sender<C>, no_data: C) -> JoinHandle<Result<Vm<C, S>, VmError>>
+    where C: Cell + Send + Sync + 'static, S: Storage<C> + Send + 'static {
+    thread::spawn(move || run_connected(vm, input, output, Some(no_data)))
+}
+
+fn run_connected<C: Cell, S: Storage<C>>(mut vm: Vm<C, S>, input: Receiver<C>, output: Sender<C>, no_data: Option<C>) -> Result<Vm<C, S>, VmError> {
+    loop {
+        match vm.run()? {
+            StepResult::Output(value) => {
+                let _ = output.send(value);
+            },
+            StepResult::NeedInput => {
+                let value = match &no_data {
+                    Some(no_data) => match input.try_recv() {
+                        Ok(value) => Some(value),
+                        Err(TryRecvError::Empty) => {
+                            thread::yield_now();
+                            Some(no_data.clone())
+                        },
+                        Err(TryRecvError::Disconnected) => None,
+                    },
+                    None => input.recv().ok(),
+                };
+
+                match value {
+                    Some(value) => vm.push_input(value),
+                    None => return Err(VmError::MissingInput { ip: vm.ip() }),
+                }
+            },
+            StepResult::Halted => return Ok(vm),
+            StepResult::OutOfFuel { ip } => return Err(VmError::OutOfFuel { ip }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::sync::mpsc::channel;
+    use crate::assemble;
+
+    #[test]
+    fn test_feedback_loop() {
+        let program = vec![3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,
+            27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5];
+
+        let (mut senders, mut receivers): (Vec<Sender<i64>>, Vec<Receiver<i64>>) = (0..6).map(|_| channel()).unzip();
+        for (sender, phase) in senders.iter().zip(&[9, 8, 7, 6, 5]) {
+            sender.send(*phase).unwrap();
+        }
+        senders[0].send(0).unwrap();
+
+        // The last amplifier sends its output back to the first one through
+        // this thread, until it halts and hangs up
+        let feedback = receivers.pop().unwrap();
+        let mut outputs = senders[1..5].to_vec();
+        outputs.push(senders.pop().unwrap());
+
+        let handles: Vec<_> = receivers.into_iter().zip(outputs).map(|(input, output)| {
+            spawn_vm(Vm::new(program.clone()), input, output)
+        }).collect();
+
+        let mut last_signal = 0;
+        for signal in feedback.iter() {
+            last_signal = signal;
+            let _ = senders[0].send(signal);
+        }
+
+        for handle in handles {
+            assert!(handle.join().unwrap().is_ok());
+        }
+        assert_eq!(last_signal, 139629729);
+    }
+
+    #[test]
+    fn test_polling() {
+        let program = assemble("
+            wait:   IN -> [value]
+                    EQ [value], #-1 -> [empty]
+                    JT [empty], #wait
+                    OUT [value]
+                    HALT
+            value:  DATA 0
+            empty:  DATA 0
+        ").unwrap();
+
+        let (input, receiver) = channel();
+        let (sender, output) = channel();
+        let handle = spawn_polling_vm(Vm::new(program), receiver, sender, -1);
+
+        input.send(42).unwrap();
+        assert_eq!(output.recv(), Ok(42));
+        assert!(handle.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_disconnected_input() {
+        let (input, receiver) = channel();
+        let (sender, output) = channel();
+        let handle = spawn_vm(Vm::new(vec![3, 9, 4, 9, 3, 9, 4, 9, 99, 0]), receiver, sender);
+
+        input.send(5).unwrap();
+        drop(input);
+        assert_eq!(output.recv(), Ok(5));
+        assert_eq!(handle.join().unwrap().err(), Some(VmError::MissingInput { ip: 4 }));
+    }
+}
@@ -0,0 +1,147 @@
+use std::io::{self, Write};
+use std::ops::Range;
+
+use crate::{mnemonic, parameter_count, Cell, ExecutionEvent, Observer, Symbols};
+
+/// Observer writing a line for every executed instruction, like
+/// `0012 ADD 3 5 100 [100]=8`. After the instruction and its parameter values
+/// come its effects: `in=`, `[address]=value` writes, `rb=` for the new
+/// relative base, `out=` and `jump=` when it didn't continue with the next
+/// instruction. Named addresses are written like `[386 score]=8`.
+pub struct Tracer<W: Write> {
+    writer: W,
+    range: Option<Range<usize>>,
+    symbols: Symbols,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(writer: W) -> Tracer<W> {
+        Tracer { writer, range: None, symbols: Symbols::new(), error: None }
+    }
+
+    /// Only traces instructions with an address in the range.
+    pub fn with_range(mut self, range: Range<usize>) -> Tracer<W> {
+        self.range = Some(range);
+        self
+    }
+
+    pub fn with_symbols(mut self, symbols: Symbols) -> Tracer<W> {
+        self.symbols = symbols;
+        self
+    }
+
+    /// Flushes the trace and returns the writer, or the first error that
+    /// happened while writing it.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+pub fn format_event<C: Cell>(event: &ExecutionEvent<C>) -> String {
+    format_event_with_symbols(event, &Symbols::new())
+}
+
+pub fn format_event_with_symbols<C: Cell>(event: &ExecutionEvent<C>, symbols: &Symbols) -> String {
+    let count = parameter_count(event.opcode).unwrap_or(0);
+    let mut line = format!("{:04} {}", event.ip, mnemonic(event.opcode).unwrap_or("???"));
+    for parameter in &event.parameters[..count] {
+        line += &format!(" {}", parameter);
+    }
+
+    if let Some(value) = &event.input {
+        line += &format!(" in={}", value);
+    }
+    if let Some((address, value)) = &event.write {
+        match symbols.name(*address) {
+            Some(name) => line += &format!(" [{} {}]={}", address, name, value),
+            None => line += &format!(" [{}]={}", address, value),
+        }
+    }
+    if let Some(relative_base) = &event.relative_base {
+        line += &format!(" rb={}", relative_base);
+    }
+    if let Some(value) = &event.output {
+        line += &format!(" out={}", value);
+    }
+    if event.next_ip != event.ip + count + 1 && event.next_ip != event.ip {
+        line += &format!(" jump={}", event.next_ip);
+    }
+
+    line
+}
+
+impl<C: Cell, W: Write> Observer<C> for Tracer<W> {
+    fn observe(&mut self, event: &ExecutionEvent<C>) {
+        if self.error.is_some() || self.range.as_ref().is_some_and(|range| !range.contains(&event.ip)) {
+            return;
+        }
+
+        if let Err(error) = writeln!(self.writer, "{}", format_event_with_symbols(event, &self.symbols)) {
+            self.error = Some(error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{assemble, Vm, StepResult};
+
+    #[test]
+    fn test_trace() {
+        let program = assemble("
+                    IN -> [value]
+                    ARB #2
+            loop:   ADD [value], #-1 -> [value]
+                    OUT [value]
+                    JT [value], #loop
+                    HALT
+            value:  DATA 0
+        ").unwrap();
+
+        let mut vm = Vm::new(program);
+        vm.push_input(2);
+        let mut tracer = Tracer::new(Vec::new());
+        assert_eq!(vm.run_until_input_observed(&mut tracer), Ok((vec![1, 0], StepResult::Halted)));
+
+        assert_eq!(String::from_utf8(tracer.finish().unwrap()).unwrap(), "\
+0000 IN 14 in=2 [14]=2
+0002 ARB 2 rb=2
+0004 ADD 2 -1 14 [14]=1
+0008 OUT 1 out=1
+0010 JT 1 4 jump=4
+0004 ADD 1 -1 14 [14]=0
+0008 OUT 0 out=0
+0010 JT 0 4
+0013 HALT
+");
+    }
+
+    #[test]
+    fn test_trace_range() {
+        let mut vm = Vm::new(vec![1101, 1, 2, 10, 104, 7, 1105, 0, 0, 99]);
+        let mut tracer = Tracer::new(Vec::new()).with_range(4..8);
+        assert_eq!(vm.run_until_input_observed(&mut tracer), Ok((vec![7], StepResult::Halted)));
+
+        assert_eq!(String::from_utf8(tracer.finish().unwrap()).unwrap(), "0004 OUT 7 out=7\n0006 JT 0 0\n");
+    }
+
+    #[test]
+    fn test_trace_symbols() {
+        let mut symbols = Symbols::new();
+        symbols.add(10..11, "sum");
+
+        let mut vm = Vm::new(vec![1101, 1, 2, 10, 99]);
+        let mut tracer = Tracer::new(Vec::new()).with_symbols(symbols);
+        assert_eq!(vm.run_until_input_observed(&mut tracer), Ok((vec![], StepResult::Halted)));
+
+        assert_eq!(String::from_utf8(tracer.finish().unwrap()).unwrap(), "0000 ADD 1 2 10 [10 sum]=3\n0004 HALT\n");
+    }
+}
@@ -0,0 +1,477 @@
+use std::collections::VecDeque;
+
+use crate::{Cell, VmError};
+use crate::{ADD, MULTIPLY, INPUT, OUTPUT, JMP_TRUE, JMP_FALSE, LESS_THAN, EQUALS, ADJUST_BASE, FINISH};
+use crate::memory::{Memory, Op, to_address};
+use crate::storage::{Storage, PagedMemory};
+
+/// State the virtual machine is in when it stops running.
+#[derive(PartialEq, Debug)]
+pub enum StepResult<C: Cell = i64> {
+    NeedInput,
+    Output(C),
+    Halted,
+    /// The fuel ran out before the instruction at `ip` could be executed.
+    OutOfFuel { ip: usize },
+}
+
+/// Everything a single executed instruction did. Parameters hold the values
+/// that were read, or the address for parameters that are written to, and
+/// reads the addresses of the parameters that weren't immediate.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct ExecutionEvent<C: Cell = i64> {
+    pub ip: usize,
+    pub opcode: i64,
+    pub parameters: [C; 3],
+    pub reads: [Option<usize>; 2],
+    pub write: Option<(usize, C)>,
+    pub relative_base: Option<C>,
+    pub input: Option<C>,
+    pub output: Option<C>,
+    pub next_ip: usize,
+}
+
+/// State after a single instruction, together with what it did.
+type Step<C> = (Option<StepResult<C>>, Option<ExecutionEvent<C>>);
+
+/// Receives an event for every instruction the virtual machine executes.
+pub trait Observer<C: Cell = i64> {
+    fn observe(&mut self, event: &ExecutionEvent<C>);
+}
+
+impl<C: Cell> Observer<C> for () {
+    fn observe(&mut self, _event: &ExecutionEvent<C>) {}
+}
+
+impl<C: Cell, O: Observer<C>> Observer<C> for Option<O> {
+    fn observe(&mut self, event: &ExecutionEvent<C>) {
+        if let Some(observer) = self {
+            observer.observe(event);
+        }
+    }
+}
+
+/// Intcode virtual machine, which keeps track of its own instruction pointer
+/// and pending input between runs. Cloning a `Vm` takes a snapshot of its
+/// complete state, which can be resumed independently of the original.
+/// With fuel set, every executed instruction except HALT uses up one unit of it.
+/// Memory cells hold `i64` values by default, see `Cell` for the others.
+#[derive(Clone)]
+pub struct Vm<C: Cell = i64, S: Storage<C> = PagedMemory<C>> {
+    pub memory: Memory<C, S>,
+    pub(crate) ip: usize,
+    pub(crate) input: VecDeque<C>,
+    pub(crate) fuel: Option<u64>,
+}
+
+impl Vm {
+    pub fn new(program: Vec<i64>) -> Vm {
+        Vm::from_memory(Memory::new(program))
+    }
+}
+
+impl<C: Cell, S: Storage<C>> Vm<C, S> {
+    pub fn from_memory(memory: Memory<C, S>) -> Vm<C, S> {
+        Vm { memory, ip: 0, input: VecDeque::new(), fuel: None }
+    }
+
+    pub fn from_program(program: Vec<C>) -> Vm<C, S> {
+        Vm::from_memory(Memory::with_storage(program))
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    /// Remaining number of instructions the machine can execute, or `None`
+    /// when it isn't limited.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Adds fuel to limited machines, so they can continue after running out.
+    pub fn add_fuel(&mut self, fuel: u64) {
+        if let Some(remaining) = self.fuel {
+            self.fuel = Some(remaining + fuel);
+        }
+    }
+
+    /// Queues a value to be read by the program after all previously queued input.
+    pub fn push_input(&mut self, value: C) {
+        self.input.push_back(value);
+    }
+
+    pub fn push_inputs(&mut self, values: &[C]) {
+        self.input.extend(values.iter().cloned());
+    }
+
+    /// Input that was queued, but not read by the program yet.
+    pub fn pending_input(&self) -> &VecDeque<C> {
+        &self.input
+    }
+
+    /// Runs until the program needs more input than queued, produces an
+    /// output, halts or runs out of fuel.
+    pub fn run(&mut self) -> Result<StepResult<C>, VmError> {
+        self.run_observed(&mut ())
+    }
+
+    /// Same as `run`, but every executed instruction is reported to the observer.
+    pub fn run_observed<O: Observer<C>>(&mut self, observer: &mut O) -> Result<StepResult<C>, VmError> {
+        loop {
+            let (result, event) = self.step_event()?;
+            if let Some(event) = event {
+                observer.observe(&event);
+            }
+
+            if let Some(result) = result {
+                return Ok(result);
+            }
+        }
+    }
+
+    /// Keeps running through outputs until the program needs more input than
+    /// available, halts or runs out of fuel. Returns all the outputs together with the state
+    /// the machine stopped in, which is never `StepResult::Output`.
+    pub fn run_until_input(&mut self) -> Result<(Vec<C>, StepResult<C>), VmError> {
+        self.run_until_input_observed(&mut ())
+    }
+
+    pub fn run_until_input_observed<O: Observer<C>>(&mut self, observer: &mut O) -> Result<(Vec<C>, StepResult<C>), VmError> {
+        let mut output = Vec::new();
+        loop {
+            match self.run_observed(observer)? {
+                StepResult::Output(value) => output.push(value),
+                state => return Ok((output, state)),
+            }
+        }
+    }
+
+    /// Runs until the program halts and returns all of its output.
+    /// Running out of input is reported as `VmError::MissingInput`, and
+    /// running out of fuel as `VmError::OutOfFuel`.
+    pub fn run_to_end(&mut self) -> Result<Vec<C>, VmError> {
+        match self.run_until_input()? {
+            (output, StepResult::Halted) => Ok(output),
+            (_, StepResult::OutOfFuel { ip }) => Err(VmError::OutOfFuel { ip }),
+            _ => Err(VmError::MissingInput { ip: self.ip }),
+        }
+    }
+
+    /// Executes a single instruction. Returns the state the machine stopped in,
+    /// or `None` if it can continue executing.
+    pub fn step(&mut self) -> Result<Option<StepResult<C>>, VmError> {
+        self.step_event().map(|(result, _)| result)
+    }
+
+    /// Executes a single instruction like `step`, and also returns what the
+    /// instruction did. There is no event when the machine waits for input.
+    pub fn step_event(&mut self) -> Result<Step<C>, VmError> {
+        let pic = self.ip;
+        if self.fuel == Some(0) && !self.halting() {
+            return Ok((Some(StepResult::OutOfFuel { ip: pic }), None));
+        }
+
+        let memory = &mut self.memory;
+        let op = memory.fetch_op(pic)?;
+        let opcode = op.opcode;
+
+        let mut event = ExecutionEvent {
+            ip: pic,
+            opcode,
+            parameters: Default::default(),
+            reads: [None; 2],
+            write: None,
+            relative_base: None,
+            input: None,
+            output: None,
+            next_ip: pic,
+        };
+        let mut result = None;
+
+        match opcode {
+            ADD | MULTIPLY | LESS_THAN | EQUALS => {
+                let (param1, param2, dest) = memory.load_three_params(pic, &op)?;
+                event.reads = read_addresses(memory, pic, &op, 2);
+                let value = match opcode {
+                    ADD => param1.try_add(&param2),
+                    MULTIPLY => param1.try_mul(&param2),
+                    LESS_THAN => Some(C::from_i64(if param1 < param2 { 1 } else { 0 })),
+                    _ => Some(C::from_i64(if param1 == param2 { 1 } else { 0 })),
+                };
+                let value = match value {
+                    Some(value) => value,
+                    None => return Err(VmError::Overflow { ip: pic }),
+                };
+
+                memory.write_memory(dest, value.clone());
+                event.parameters = [param1, param2, C::from_i64(dest as i64)];
+                event.write = Some((dest, value));
+                self.ip += 4;
+            },
+            INPUT => {
+                let dest = memory.destination(pic, &op.params[0])?;
+
+                let input_number: C = match self.input.pop_front() {
+                    Some(num) => num,
+                    None => return Ok((Some(StepResult::NeedInput), None)),
+                };
+
+                memory.write_memory(dest, input_number.clone());
+                event.parameters[0] = C::from_i64(dest as i64);
+                event.write = Some((dest, input_number.clone()));
+                event.input = Some(input_number);
+                self.ip += 2;
+            },
+            OUTPUT => {
+                let param1 = memory.load_one_param(pic, &op)?;
+                event.reads = read_addresses(memory, pic, &op, 1);
+                event.parameters[0] = param1.clone();
+                event.output = Some(param1.clone());
+                self.ip += 2;
+
+                result = Some(StepResult::Output(param1));
+            },
+            JMP_TRUE | JMP_FALSE => {
+                let (param1, param2) = memory.load_two_params(pic, &op)?;
+                event.reads = read_addresses(memory, pic, &op, 2);
+
+                if param1.is_zero() != (opcode == JMP_TRUE) {
+                    self.ip = to_address(pic, &param2)?;
+                }
+                else {
+                    self.ip += 3;
+                }
+                event.parameters = [param1, param2, C::default()];
+            },
+            ADJUST_BASE => {
+                let param1 = memory.load_one_param(pic, &op)?;
+                event.reads = read_addresses(memory, pic, &op, 1);
+                memory.relative_base = match memory.relative_base.try_add(&param1) {
+                    Some(relative_base) => relative_base,
+                    None => return Err(VmError::Overflow { ip: pic }),
+                };
+                event.parameters[0] = param1;
+                event.relative_base = Some(memory.relative_base.clone());
+
+                self.ip += 2;
+            }
+            FINISH => result = Some(StepResult::Halted),
+            _ => return Err(VmError::UnknownOpcode { ip: pic, opcode }),
+        };
+
+        event.next_ip = self.ip;
+        if let (Some(fuel), false) = (self.fuel.as_mut(), opcode == FINISH) {
+            *fuel -= 1;
+        }
+
+        Ok((result, Some(event)))
+    }
+
+    /// Halting doesn't use up fuel, as the machine stays at the HALT, so
+    /// running a halted machine again only halts it again.
+    fn halting(&self) -> bool {
+        self.memory.fetch_op(self.ip).is_ok_and(|op| op.opcode == FINISH)
+    }
+}
+
+fn read_addresses<C: Cell, S: Storage<C>>(memory: &Memory<C, S>, pic: usize, op: &Op<C>, count: usize) -> [Option<usize>; 2] {
+    let first = memory.param_address(pic, &op.params[0]);
+    let second = if count > 1 { memory.param_address(pic, &op.params[1]) } else { None };
+    [first, second]
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{to_cells, BigInt, Checked};
+
+    #[test]
+    fn test_position_mode_add() {
+        let mut vm = Vm::new(vec![1, 0, 0, 0, 99]);
+        assert_eq!(vm.run(), Ok(StepResult::Halted));
+        assert_eq!(vm.memory.program(), &[2, 0, 0, 0, 99]);
+    }
+
+    #[test]
+    fn test_input_and_compare() {
+        let program = vec![3,9,8,9,10,9,4,9,99,-1,8];
+
+        let mut vm = Vm::new(program.clone());
+        vm.push_input(8);
+        assert_eq!(vm.run_to_end(), Ok(vec![1]));
+
+        let mut vm = Vm::new(program);
+        vm.push_input(9);
+        assert_eq!(vm.run_to_end(), Ok(vec![0]));
+    }
+
+    #[test]
+    fn test_pause_and_resume() {
+        let mut vm = Vm::new(vec![3, 7, 4, 7, 104, 5, 99, 0]);
+        assert_eq!(vm.run(), Ok(StepResult::NeedInput));
+        assert_eq!(vm.ip(), 0);
+
+        vm.push_input(42);
+        assert_eq!(vm.run(), Ok(StepResult::Output(42)));
+        assert_eq!(vm.run(), Ok(StepResult::Output(5)));
+        assert_eq!(vm.run(), Ok(StepResult::Halted));
+        assert_eq!(vm.run(), Ok(StepResult::Halted));
+    }
+
+    #[test]
+    fn test_input_is_read_in_order() {
+        let mut vm = Vm::new(vec![3, 0, 3, 1, 4, 0, 4, 1, 99]);
+        vm.push_inputs(&[10, 20]);
+        assert_eq!(vm.run_to_end(), Ok(vec![10, 20]));
+    }
+
+    #[test]
+    fn test_leftover_input_is_kept() {
+        let mut vm = Vm::new(vec![3, 11, 4, 11, 3, 11, 4, 11, 1105, 1, 0, 0]);
+        vm.push_inputs(&[1, 2, 3]);
+        assert_eq!(vm.run(), Ok(StepResult::Output(1)));
+        assert_eq!(vm.pending_input(), &VecDeque::from(vec![2, 3]));
+
+        assert_eq!(vm.run_until_input(), Ok((vec![2, 3], StepResult::NeedInput)));
+        assert!(vm.pending_input().is_empty());
+
+        vm.push_input(4);
+        assert_eq!(vm.run(), Ok(StepResult::Output(4)));
+    }
+
+    #[test]
+    fn test_run_until_input() {
+        let mut vm = Vm::new(vec![104, 1, 104, 2, 3, 9, 4, 9, 99, 0]);
+        assert_eq!(vm.run_until_input(), Ok((vec![1, 2], StepResult::NeedInput)));
+
+        vm.push_input(3);
+        assert_eq!(vm.run_until_input(), Ok((vec![3], StepResult::Halted)));
+    }
+
+    #[test]
+    fn test_single_step() {
+        let mut vm = Vm::new(vec![1101, 2, 3, 0, 99]);
+        assert_eq!(vm.step(), Ok(None));
+        assert_eq!(vm.ip(), 4);
+        assert_eq!(vm.memory.program()[0], 5);
+        assert_eq!(vm.step(), Ok(Some(StepResult::Halted)));
+    }
+
+    #[test]
+    fn test_relative_mode_and_virtual_memory() {
+        let program = vec![109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99];
+        assert_eq!(Vm::new(program.clone()).run_to_end(), Ok(program));
+    }
+
+    #[test]
+    fn test_self_modifying_code() {
+        // Increments the parameter of its first instruction
+        let program = vec![104, 7, 1001, 1, 1, 1, 1007, 1, 9, 14, 1005, 14, 0, 99, 0];
+        assert_eq!(Vm::new(program.clone()).run_to_end(), Ok(vec![7, 8]));
+
+        // Overwrites its first instruction with a halt
+        let program = vec![104, 1, 1101, 0, 99, 0, 1105, 1, 0];
+        assert_eq!(Vm::new(program.clone()).run_to_end(), Ok(vec![1]));
+
+        let mut uncached = Vm::new(program);
+        uncached.memory.disable_instruction_cache();
+        assert_eq!(uncached.run_to_end(), Ok(vec![1]));
+    }
+
+    #[test]
+    fn test_program_edited_after_decoding() {
+        let mut vm = Vm::new(vec![104, 5, 1105, 1, 0]);
+        assert_eq!(vm.run(), Ok(StepResult::Output(5)));
+        assert_eq!(vm.run(), Ok(StepResult::Output(5)));
+
+        vm.memory.write_memory(0, 99);
+        assert_eq!(vm.run(), Ok(StepResult::Halted));
+    }
+
+    #[test]
+    fn test_fuel() {
+        let mut vm = Vm::new(vec![104, 1, 1105, 1, 0]);
+        vm.set_fuel(Some(3));
+        assert_eq!(vm.run_until_input(), Ok((vec![1, 1], StepResult::OutOfFuel { ip: 2 })));
+        assert_eq!(vm.run(), Ok(StepResult::OutOfFuel { ip: 2 }));
+        assert_eq!(vm.fuel(), Some(0));
+
+        vm.add_fuel(2);
+        assert_eq!(vm.run(), Ok(StepResult::Output(1)));
+        assert_eq!(vm.run(), Ok(StepResult::OutOfFuel { ip: 2 }));
+        assert_eq!(vm.run_to_end(), Err(VmError::OutOfFuel { ip: 2 }));
+
+        // Waiting for input doesn't use up fuel
+        let mut vm = Vm::new(vec![3, 5, 4, 5, 99, 0]);
+        vm.set_fuel(Some(3));
+        assert_eq!(vm.run(), Ok(StepResult::NeedInput));
+        assert_eq!(vm.fuel(), Some(3));
+
+        vm.push_input(8);
+        assert_eq!(vm.run_to_end(), Ok(vec![8]));
+        assert_eq!(vm.fuel(), Some(1));
+
+        // Neither does halting, so halted machines can be run again
+        vm.set_fuel(Some(0));
+        assert_eq!(vm.run(), Ok(StepResult::Halted));
+        assert_eq!(vm.run_to_end(), Ok(vec![]));
+        assert_eq!(vm.fuel(), Some(0));
+    }
+
+    #[test]
+    fn test_cell_types() {
+        let program = [1102, 1 << 32, 1 << 31, 7, 4, 7, 99, 0];
+        assert_eq!(Vm::new(program.to_vec()).run_to_end(), Ok(vec![i64::MIN]));
+
+        let mut checked: Vm<Checked> = Vm::from_program(to_cells(&program));
+        assert_eq!(checked.run_to_end(), Err(VmError::Overflow { ip: 0 }));
+
+        let mut big: Vm<BigInt> = Vm::from_program(to_cells(&program));
+        assert_eq!(big.run_to_end(), Ok(vec!["9223372036854775808".parse().unwrap()]));
+
+        // Addresses have to fit into i64
+        let mut big: Vm<BigInt> = Vm::from_program(to_cells(&[109, 1 << 62, 109, 1 << 62, 204, 0, 99]));
+        assert_eq!(big.run_to_end(), Err(VmError::Overflow { ip: 4 }));
+    }
+
+    #[test]
+    fn test_unknown_opcode() {
+        let mut vm = Vm::new(vec![1101, 1, 1, 5, 42, 0]);
+        assert_eq!(vm.run(), Err(VmError::UnknownOpcode { ip: 4, opcode: 42 }));
+    }
+
+    #[test]
+    fn test_invalid_parameter_mode() {
+        let mut vm = Vm::new(vec![104, 0, 304, 0, 99]);
+        assert_eq!(vm.run(), Ok(StepResult::Output(0)));
+        assert_eq!(vm.run(), Err(VmError::InvalidParameterMode { ip: 2, mode: 3 }));
+    }
+
+    #[test]
+    fn test_negative_address() {
+        let mut vm = Vm::new(vec![4, -3, 99]);
+        assert_eq!(vm.run(), Err(VmError::NegativeAddress { ip: 0, address: -3 }));
+
+        let mut vm = Vm::new(vec![1105, 1, -7, 99]);
+        assert_eq!(vm.run(), Err(VmError::NegativeAddress { ip: 0, address: -7 }));
+    }
+
+    #[test]
+    fn test_immediate_mode_write() {
+        let mut vm = Vm::new(vec![11101, 1, 1, 0, 99]);
+        assert_eq!(vm.run(), Err(VmError::ImmediateModeWrite { ip: 0 }));
+    }
+
+    #[test]
+    fn test_missing_input() {
+        let mut vm = Vm::new(vec![3, 7, 3, 8, 4, 8, 99, 0, 0]);
+        vm.push_input(5);
+        assert_eq!(vm.run_to_end(), Err(VmError::MissingInput { ip: 2 }));
+    }
+}
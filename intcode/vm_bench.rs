@@ -0,0 +1,144 @@
+//! Compares the paged memory with a `HashMap`, and running with the decoded
+//! instruction cache with running without it, on the Intcode puzzle inputs.
+//! Run with `cargo bench`.
+
+extern crate intcode;
+
+use std::collections::HashMap;
+use std::fs::read;
+use std::time::{Duration, Instant};
+use intcode::{get_program, group_output, Memory, Network, PacketBus, PagedMemory, StepResult, Storage, Vm};
+
+fn load(path: &str) -> Vec<i64> {
+    let raw_input = match read(path) {
+        Err(_) => panic!("Can't read {}!", path),
+        Ok(file) => file,
+    };
+
+    get_program(String::from_utf8_lossy(&raw_input).to_string())
+}
+
+fn new_vm<S: Storage>(program: &[i64]) -> Vm<i64, S> {
+    Vm::from_memory(Memory::with_storage(program.to_vec()))
+}
+
+fn uncached_vm(program: &[i64]) -> Vm {
+    let mut vm = Vm::new(program.to_vec());
+    vm.memory.disable_instruction_cache();
+    vm
+}
+
+/// Day 9 BOOST program in sensor boost mode.
+fn boost<S: Storage>(mut vm: Vm<i64, S>) -> i64 {
+    vm.push_input(2);
+    vm.run_to_end().unwrap()[0]
+}
+
+/// Day 13 arcade, played until all the blocks are broken.
+fn arcade<S: Storage>(mut vm: Vm<i64, S>) -> i64 {
+    vm.memory.write_memory(0, 2);
+
+    let (mut ball_x, mut paddle_x, mut score) = (0, 0, 0);
+    loop {
+        let (output, state) = vm.run_until_input().unwrap();
+        for tile in group_output(&output, 3) {
+            match (tile[0], tile[2]) {
+                (-1, value) => score = value,
+                (x, 3) => paddle_x = x,
+                (x, 4) => ball_x = x,
+                _ => {},
+            }
+        }
+
+        if state == StepResult::Halted {
+            return score;
+        }
+        vm.push_input((ball_x - paddle_x).signum());
+    }
+}
+
+/// Day 19 tractor beam, probed on a 50x50 grid.
+fn beam<S: Storage>(drone: Vm<i64, S>) -> i64 {
+    let mut pulled = 0;
+    for y in 0..50 {
+        for x in 0..50 {
+            let mut vm = drone.clone();
+            vm.push_inputs(&[x, y]);
+            pulled += vm.run_to_end().unwrap()[0];
+        }
+    }
+
+    pulled
+}
+
+/// Day 23 network of 50 computers, run until the NAT delivers the same Y twice.
+fn network(nic: Vm) -> i64 {
+    let computers = (0..50).map(|address| {
+        let mut vm = nic.clone();
+        vm.push_input(address);
+        vm
+    }).collect();
+
+    let mut network = Network::new(computers, PacketBus::new(50).with_nat(255));
+    let (mut delivered, mut repeated) = (None, 0);
+    network.run_observed(|packet| {
+        if packet.from.is_some() {
+            return true;
+        }
+        if delivered == Some(packet.values[1]) {
+            repeated = packet.values[1];
+            return false;
+        }
+        delivered = Some(packet.values[1]);
+        true
+    }).unwrap();
+
+    repeated
+}
+
+fn measure<F: Fn() -> i64>(workload: F) -> (i64, Duration) {
+    const RUNS: u32 = 20;
+
+    let mut result = 0;
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        result = workload();
+    }
+
+    (result, start.elapsed() / RUNS)
+}
+
+/// Label, starting state and workload of a measured run.
+type Setup<S> = (&'static str, Vm<i64, S>, fn(Vm<i64, S>) -> i64);
+
+fn compare<A: Storage, B: Storage>(name: &str, first: Setup<A>, second: Setup<B>) {
+    let (first_result, first_time) = measure(|| (first.2)(first.1.clone()));
+    let (second_result, second_time) = measure(|| (second.2)(second.1.clone()));
+    assert_eq!(first_result, second_result, "Results of {} differ", name);
+
+    println!("{:<8} {:<8} {:>8.2} ms   {:<8} {:>8.2} ms   speedup {:.2}x",
+        name,
+        first.0,
+        first_time.as_secs_f64() * 1000.0,
+        second.0,
+        second_time.as_secs_f64() * 1000.0,
+        second_time.as_secs_f64() / first_time.as_secs_f64());
+}
+
+fn main() {
+    let day09 = load("../day09/input.txt");
+    let day13 = load("../day13/input.txt");
+    let day19 = load("../day19/input.txt");
+    let day23 = load("../day23/input.txt");
+
+    println!("Memory:");
+    compare("day09", ("paged", new_vm::<PagedMemory>(&day09), boost), ("hash map", new_vm::<HashMap<usize, i64>>(&day09), boost));
+    compare("day13", ("paged", new_vm::<PagedMemory>(&day13), arcade), ("hash map", new_vm::<HashMap<usize, i64>>(&day13), arcade));
+    compare("day19", ("paged", new_vm::<PagedMemory>(&day19), beam), ("hash map", new_vm::<HashMap<usize, i64>>(&day19), beam));
+
+    println!("Instruction cache:");
+    compare("day09", ("cached", new_vm::<PagedMemory>(&day09), boost), ("uncached", uncached_vm(&day09), boost));
+    compare("day13", ("cached", new_vm::<PagedMemory>(&day13), arcade), ("uncached", uncached_vm(&day13), arcade));
+    compare("day19", ("cached", new_vm::<PagedMemory>(&day19), beam), ("uncached", uncached_vm(&day19), beam));
+    compare("day23", ("cached", new_vm::<PagedMemory>(&day23), network), ("uncached", uncached_vm(&day23), network));
+}
@@ -0,0 +1,157 @@
+use std::io::{self, Write};
+use std::ops::Range;
+
+use crate::{mnemonic, ExecutionEvent, Observer, Symbols};
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Range of addresses whose reads, writes or both are watched.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Watchpoint {
+    pub range: Range<usize>,
+    pub reads: bool,
+    pub writes: bool,
+}
+
+/// Watched address accessed by the instruction at `ip`, with the value that
+/// was read or written.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct WatchHit {
+    pub ip: usize,
+    pub opcode: i64,
+    pub address: usize,
+    pub access: Access,
+    pub value: i64,
+}
+
+impl Watchpoint {
+    pub fn new(range: Range<usize>, reads: bool, writes: bool) -> Watchpoint {
+        Watchpoint { range, reads, writes }
+    }
+
+    /// Accesses of the executed instruction to the watched addresses.
+    pub fn hits(&self, event: &ExecutionEvent) -> Vec<WatchHit> {
+        let mut hits = Vec::new();
+        let hit = |address: usize, access: Access, value: i64| WatchHit { ip: event.ip, opcode: event.opcode, address, access, value };
+
+        if self.reads {
+            for (read, value) in event.reads.iter().zip(&event.parameters) {
+                if let Some(address) = read.filter(|address| self.range.contains(address)) {
+                    hits.push(hit(address, Access::Read, *value));
+                }
+            }
+        }
+        if self.writes {
+            if let Some((address, value)) = event.write.filter(|(address, _)| self.range.contains(address)) {
+                hits.push(hit(address, Access::Write, value));
+            }
+        }
+
+        hits
+    }
+}
+
+/// Describes the hit like `0571 ADD write [386 score]=1234`.
+pub fn format_hit(hit: &WatchHit, symbols: &Symbols) -> String {
+    let access = match hit.access {
+        Access::Read => "read",
+        Access::Write => "write",
+    };
+    let address = match symbols.name(hit.address) {
+        Some(name) => format!("{} {}", hit.address, name),
+        None => hit.address.to_string(),
+    };
+
+    format!("{:04} {} {} [{}]={}", hit.ip, mnemonic(hit.opcode).unwrap_or("???"), access, address, hit.value)
+}
+
+/// Observer logging a line for every access to watched memory.
+pub struct Watcher<W: Write> {
+    writer: W,
+    watchpoints: Vec<Watchpoint>,
+    symbols: Symbols,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Watcher<W> {
+    pub fn new(writer: W, watchpoints: Vec<Watchpoint>) -> Watcher<W> {
+        Watcher { writer, watchpoints, symbols: Symbols::new(), error: None }
+    }
+
+    /// Names the watched addresses in the log.
+    pub fn with_symbols(mut self, symbols: Symbols) -> Watcher<W> {
+        self.symbols = symbols;
+        self
+    }
+
+    /// Flushes the log and returns the writer, or the first error that
+    /// happened while writing it.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Observer for Watcher<W> {
+    fn observe(&mut self, event: &ExecutionEvent) {
+        if self.error.is_some() {
+            return;
+        }
+
+        for watchpoint in &self.watchpoints {
+            for hit in watchpoint.hits(event) {
+                if let Err(error) = writeln!(self.writer, "{}", format_hit(&hit, &self.symbols)) {
+                    self.error = Some(error);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{assemble, Vm, StepResult};
+
+    #[test]
+    fn test_watcher() {
+        let program = assemble("
+                    ARB #total
+            loop:   IN -> [value]
+                    ADD [value], [rb+0] -> [rb+0]
+                    OUT [total]
+                    JT [value], #loop
+                    HALT
+            value:  DATA 0
+            total:  DATA 0
+        ").unwrap();
+
+        let mut symbols = Symbols::new();
+        symbols.add(15..16, "total");
+
+        let mut vm = Vm::new(program);
+        vm.push_inputs(&[4, 0]);
+        let mut watcher = Watcher::new(Vec::new(), vec![Watchpoint::new(15..16, false, true), Watchpoint::new(14..15, true, false)])
+            .with_symbols(symbols);
+        assert_eq!(vm.run_until_input_observed(&mut watcher), Ok((vec![4, 4], StepResult::Halted)));
+
+        assert_eq!(String::from_utf8(watcher.finish().unwrap()).unwrap(), "\
+0004 ADD write [15 total]=4
+0004 ADD read [14]=4
+0010 JT read [14]=4
+0004 ADD write [15 total]=4
+0004 ADD read [14]=0
+0010 JT read [14]=0
+");
+    }
+}